@@ -0,0 +1,108 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::io::{BufReader,BufWriter};
+use std::path::Path;
+use std::time::SystemTime;
+use std::{fs,io};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::Settings;
+
+// Files changed this recently are not cached, since a later change within
+// the same timestamp tick would go unnoticed.
+const RACY_SECONDS: i64 = 2;
+
+// Read a cache file written by `store`. The cache is empty if it is being
+// refreshed, cannot be read or was written with a different `version` of
+// its format.
+pub fn load<T: DeserializeOwned + Default>(path: &Path, version: u32) -> T {
+    let settings = Settings::get();
+    if settings.refresh_cache() {
+        return T::default();
+    }
+
+    read(path, version).unwrap_or_else(|err| {
+        if settings.verbose() && err.kind() != io::ErrorKind::NotFound {
+            eprintln!("Error reading {}: {}", path.display(), err);
+        }
+        T::default()
+    })
+}
+
+// Write a cache file, preceded by the `version` of its format.
+pub fn store<T: Serialize>(path: &Path, version: u32, data: &T) {
+    if let Err(err) = write(path, version, data) {
+        if Settings::get().verbose() {
+            eprintln!("Error writing {}: {}", path.display(), err);
+        }
+    }
+}
+
+// A file timestamp in nanoseconds, from its seconds and nanoseconds parts.
+pub fn ns(seconds: i64, nanoseconds: i64) -> i64 {
+    seconds * 1_000_000_000 + nanoseconds
+}
+
+// Whether a file with these timestamps, in nanoseconds, was changed too
+// recently for its cached data to be trusted on a later run.
+pub fn is_racy(mtime_ns: i64, ctime_ns: i64) -> bool {
+    let now = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(now) => now.as_secs() as i64,
+        Err(_) => return true
+    };
+
+    now - mtime_ns / 1_000_000_000 < RACY_SECONDS ||
+        now - ctime_ns / 1_000_000_000 < RACY_SECONDS
+}
+
+fn read<T: DeserializeOwned + Default>(path: &Path, version: u32) -> io::Result<T> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let found: u32 = bincode::deserialize_from(&mut reader).map_err(invalid_data)?;
+    if found != version {
+        return Ok(T::default());
+    }
+
+    bincode::deserialize_from(reader).map_err(invalid_data)
+}
+
+// Write the cache to a temporary file and rename it into place, so that
+// an interrupted write never leaves a truncated cache behind.
+fn write<T: Serialize>(path: &Path, version: u32, data: &T) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp_path = path.with_extension(format!("tmp.{}", std::process::id()));
+    let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+    bincode::serialize_into(&mut writer, &version).map_err(io::Error::other)?;
+    bincode::serialize_into(&mut writer, data).map_err(io::Error::other)?;
+    writer.into_inner()?.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+fn invalid_data(err: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
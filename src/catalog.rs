@@ -21,57 +21,176 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
 
+pub mod atom;
+pub mod cache;
 pub mod file;
 mod package;
 
-use std::collections::HashSet;
-use std::sync::{Arc,Mutex};
+use std::fs;
+use std::path::{Path,PathBuf};
+use std::process;
+use std::sync::mpsc;
 use walkdir::WalkDir;
 
-use crate::FileInfo;
 use crate::Settings;
+use crate::path_tree::{PathId,PathTree};
+use self::atom::{Atom,PackageName};
+use self::cache::CatalogCache;
+use self::file::{File,Record};
 use self::package::Package;
+pub use self::package::ParseError;
 
 pub struct Catalog;
 
+// The entries of every package, sorted by path so that the entries for the
+// same path recorded by different packages are adjacent.
+pub struct Records {
+    ids: Vec<PathId>,
+    records: Vec<Record>
+}
+
 impl Catalog {
-    pub fn read() -> HashSet<Box<dyn FileInfo>> {
+    // Read every entry of every package, adding their paths to the path
+    // tree, which is then sorted.
+    pub fn read(tree: &mut PathTree) -> Records {
+        let mut records = Vec::new();
+        read_packages(|files| {
+            for file in files {
+                let (path, record) = file.into_record();
+                records.push((tree.insert(&path), record));
+            }
+        });
+
+        tree.sort();
+        records.sort_by_key(|(id, _)| *id);
+        Records {
+            ids: records.iter().map(|(id, _)| *id).collect(),
+            records: records.into_iter().map(|(_, record)| record).collect()
+        }
+    }
+
+    // Find the installed packages matching an atom. Returns the names of
+    // the matching packages and the paths of their CONTENTS files.
+    pub fn find_packages(atom: &Atom) -> Vec<(PackageName, PathBuf)> {
         let settings = Settings::get();
-        let pool = threadpool::Builder::new().build();
         let walkdir = WalkDir::new(settings.pkg_dir())
             .max_depth(2)
             .min_depth(2)
+            .sort_by_file_name()
             .into_iter();
 
-        let vec = Arc::new(Mutex::new(Vec::new()));
-        for result in walkdir {
-            match result {
-                Ok(entry) => {
-                    let mut path = entry.path().to_path_buf();
-                    path.push("CONTENTS");
-                    if !path.exists() { continue; }
-                    
-                    let vec = vec.clone();
-                    let settings = Settings::get();
-                    pool.execute(move || {
-                        let mut vec = vec.lock().unwrap();
-                        vec.append(&mut Package::read(path, &settings));
+        walkdir.filter_map(|result| result.ok()).filter_map(|entry| {
+            let pf = entry.file_name().to_str()?;
+            let category = entry.path().parent()?.file_name()?.to_str()?;
+            let package = PackageName::parse(category, pf).ok()?;
+            let slot = if atom.has_slot() {
+                fs::read_to_string(entry.path().join("SLOT")).ok()
+            } else {
+                None
+            };
+
+            let contents = entry.path().join("CONTENTS");
+            if atom.matches(&package, slot.as_deref().map(str::trim)) && contents.exists() {
+                Some((package, contents))
+            } else {
+                None
+            }
+        }).collect()
+    }
+
+    pub fn read_package<P: AsRef<Path>>(contents_path: P) -> Result<Vec<File>,ParseError> {
+        let settings = Settings::get();
+        Package::read(contents_path, &settings)
+    }
+
+    // Read every entry of every package without merging entries for the
+    // same path recorded by different packages.
+    pub fn read_entries() -> Vec<File> {
+        let mut entries = Vec::new();
+        read_packages(|mut files| entries.append(&mut files));
+        entries
+    }
+
+    // Display the packages whose CONTENTS files could not be read
+    // completely on STDERR. Returns whether there were any.
+    pub fn report_damaged() -> bool {
+        let damaged = Package::damaged();
+        if damaged.is_empty() {
+            return false;
+        }
+
+        eprintln!("Damaged CONTENTS files:");
+        for damaged in &damaged {
+            match damaged.skipped {
+                0 => eprintln!("  {}: not read ({})", damaged.package, damaged.error),
+                1 => eprintln!("  {}: 1 line skipped ({})", damaged.package, damaged.error),
+                n => eprintln!("  {}: {} lines skipped (first {})", damaged.package, n, damaged.error)
+            }
+        }
+        true
+    }
+}
+
+impl Records {
+    // The entries recorded for a path.
+    pub fn get(&self, id: PathId) -> Option<&[Record]> {
+        let start = self.ids.partition_point(|other| *other < id);
+        let end = self.ids.partition_point(|other| *other <= id);
+        if start == end {
+            return None;
+        }
+
+        Some(&self.records[start..end])
+    }
+}
+
+// Read the packages on the thread pool, passing the entries of each
+// package to `f` as soon as they have been read.
+fn read_packages<F: FnMut(Vec<File>)>(mut f: F) {
+    let settings = Settings::get();
+    let pool = threadpool::Builder::new().build();
+    let walkdir = WalkDir::new(settings.pkg_dir())
+        .max_depth(2)
+        .min_depth(2)
+        .into_iter();
+
+    let (tx, rx) = mpsc::channel();
+    for result in walkdir {
+        match result {
+            Ok(entry) => {
+                let mut path = entry.path().to_path_buf();
+                path.push("CONTENTS");
+                if !path.exists() { continue; }
+
+                let tx = tx.clone();
+                let settings = Settings::get();
+                pool.execute(move || {
+                    let result = Package::read(path, &settings).map(|mut files| {
+                        files.retain(|file| settings.in_scope(file.path()));
+                        files
                     });
-                },
+                    tx.send(result).unwrap();
+                });
+            },
 
-                Err(err) => {
-                    if settings.verbose() {
-                        eprintln!("Error traversing directory tree: {}", err);
-                    }
+            Err(err) => {
+                if settings.verbose() {
+                    eprintln!("Error traversing directory tree: {}", err);
                 }
             }
         }
+    }
 
-        pool.join();
-        Arc::try_unwrap(vec).unwrap()
-            .into_inner().unwrap()
-            .into_iter()
-            .map(|file| file.to_file_info())
-            .collect()
+    drop(tx);
+    for result in rx {
+        match result {
+            Ok(files) => f(files),
+            Err(err) => {
+                eprintln!("Error reading package database: {}", err);
+                process::exit(1);
+            }
+        }
     }
+
+    CatalogCache::get().save(true);
 }
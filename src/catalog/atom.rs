@@ -0,0 +1,533 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::cmp::Ordering;
+use std::fmt;
+
+// The package name and version parsed from a `package-version` directory
+// name in the package database.
+#[derive(Clone,Debug,PartialEq)]
+pub struct PackageName {
+    category: String,
+    pn: String,
+    version: Version
+}
+
+// A package version as described by the Package Manager Specification.
+#[derive(Clone,Debug)]
+pub struct Version {
+    text: String,
+    numbers: Vec<String>,
+    letter: Option<char>,
+    suffixes: Vec<(Suffix, String)>,
+    revision: String
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord)]
+enum Suffix {
+    Alpha,
+    Beta,
+    Pre,
+    Rc,
+    P
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum Operator {
+    Less,
+    LessEqual,
+    Equal,
+    Approximate,
+    GreaterEqual,
+    Greater
+}
+
+// A package dependency specification given by the user, such as
+// `=cat/pkg-1.2*`, `>=cat/pkg-1.0` or `cat/pkg:slot`.
+#[derive(Clone,Debug)]
+pub struct Atom {
+    operator: Option<Operator>,
+    category: Option<String>,
+    pn: String,
+    version: Option<Version>,
+    glob: bool,
+    slot: Option<String>
+}
+
+#[derive(Debug)]
+pub struct AtomError {
+    input: String,
+    reason: &'static str
+}
+
+impl PackageName {
+    // Parse the category and `package-version` directory names of a
+    // package database entry.
+    pub fn parse(category: &str, pf: &str) -> Result<PackageName,AtomError> {
+        let (pn, version) = split_version(pf)
+            .ok_or_else(|| AtomError::new(pf, "missing version"))?;
+        if !is_valid_name(category) {
+            return Err(AtomError::new(category, "invalid category"));
+        }
+
+        Ok(PackageName {
+            category: category.to_string(),
+            pn: pn.to_string(),
+            version
+        })
+    }
+}
+
+impl fmt::Display for PackageName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}-{}", self.category, self.pn, self.version)
+    }
+}
+
+impl Version {
+    pub fn parse(text: &str) -> Result<Version,AtomError> {
+        let error = |reason| AtomError::new(text, reason);
+        let (rest, revision) = match text.rsplit_once("-r") {
+            Some((rest, revision)) if is_number(revision) => (rest, revision),
+            _ => (text, "0")
+        };
+
+        let mut parts = rest.split('_');
+        let base = parts.next().unwrap_or_default();
+        let (base, letter) = match base.chars().last() {
+            Some(c) if c.is_ascii_lowercase() => (&base[..base.len() - 1], Some(c)),
+            _ => (base, None)
+        };
+
+        let numbers: Vec<String> = base.split('.').map(String::from).collect();
+        if !numbers.iter().all(|number| is_number(number)) {
+            return Err(error("invalid version number"));
+        }
+
+        let mut suffixes = Vec::new();
+        for part in parts {
+            let (suffix, number) = [("alpha", Suffix::Alpha), ("beta", Suffix::Beta),
+                                    ("pre", Suffix::Pre), ("rc", Suffix::Rc),
+                                    ("p", Suffix::P)]
+                .iter()
+                .find_map(|(name, suffix)| {
+                    let number = part.strip_prefix(name)?;
+                    if number.is_empty() || is_number(number) {
+                        Some((*suffix, number))
+                    } else {
+                        None
+                    }
+                })
+                .ok_or_else(|| error("invalid version suffix"))?;
+            let number = if number.is_empty() { "0" } else { number };
+            suffixes.push((suffix, number.to_string()));
+        }
+
+        Ok(Version {
+            text: text.to_string(),
+            numbers,
+            letter,
+            suffixes,
+            revision: revision.to_string()
+        })
+    }
+
+    // Compare versions ignoring the revision.
+    fn cmp_base(&self, other: &Version) -> Ordering {
+        let mut numbers = self.numbers.iter().zip(other.numbers.iter());
+        if let Some((a, b)) = numbers.next() {
+            let ordering = cmp_integers(a, b);
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        for (a, b) in numbers {
+            let ordering = if a.starts_with('0') || b.starts_with('0') {
+                a.trim_end_matches('0').cmp(b.trim_end_matches('0'))
+            } else {
+                cmp_integers(a, b)
+            };
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        let ordering = self.numbers.len().cmp(&other.numbers.len())
+            .then(self.letter.cmp(&other.letter));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+
+        for ((a, a_num), (b, b_num)) in self.suffixes.iter().zip(other.suffixes.iter()) {
+            let ordering = a.cmp(b).then_with(|| cmp_integers(a_num, b_num));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        // A remaining suffix sorts after no suffix only if it is `_p`.
+        let len = self.suffixes.len().min(other.suffixes.len());
+        match (self.suffixes.get(len), other.suffixes.get(len)) {
+            (Some((Suffix::P, _)), None) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (None, Some((Suffix::P, _))) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            _ => Ordering::Equal
+        }
+    }
+
+    // Whether this version, followed by a wildcard, matches `other`.
+    fn is_prefix_of(&self, other: &Version) -> bool {
+        other.text.starts_with(&self.text)
+            && match other.text[self.text.len()..].chars().next() {
+                Some(c) => !c.is_ascii_digit() || !self.text.ends_with(|c: char| c.is_ascii_digit()),
+                None => true
+            }
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Version) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Version) -> Ordering {
+        self.cmp_base(other)
+            .then_with(|| cmp_integers(&self.revision, &other.revision))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Atom {
+    pub fn parse(text: &str) -> Result<Atom,AtomError> {
+        let error = |reason| AtomError::new(text, reason);
+        let (rest, slot) = match text.split_once(':') {
+            Some((_, "")) => return Err(error("empty slot")),
+            Some((rest, slot)) => (rest, Some(slot.to_string())),
+            None => (text, None)
+        };
+
+        let (operator, rest) = [("<=", Operator::LessEqual), (">=", Operator::GreaterEqual),
+                                ("<", Operator::Less), (">", Operator::Greater),
+                                ("=", Operator::Equal), ("~", Operator::Approximate)]
+            .iter()
+            .find_map(|(prefix, operator)| {
+                rest.strip_prefix(prefix).map(|rest| (Some(*operator), rest))
+            })
+            .unwrap_or((None, rest));
+
+        let (rest, glob) = match rest.strip_suffix('*') {
+            Some(rest) => (rest, true),
+            None => (rest, false)
+        };
+
+        if glob && operator != Some(Operator::Equal) {
+            return Err(error("wildcard requires the = operator"));
+        }
+
+        let (category, name) = match rest.split_once('/') {
+            Some((category, name)) if is_valid_name(category) => {
+                (Some(category.to_string()), name)
+            },
+
+            Some(_) => return Err(error("invalid category")),
+            None => (None, rest)
+        };
+
+        let (pn, version) = if operator.is_some() {
+            let (pn, version) = split_version(name)
+                .ok_or_else(|| error("missing version"))?;
+            (pn, Some(version))
+        } else if split_version(name).is_some() {
+            return Err(error("version requires an operator"));
+        } else {
+            (name, None)
+        };
+
+        if operator == Some(Operator::Approximate) && version.as_ref().unwrap().revision != "0" {
+            return Err(error("~ does not accept a revision"));
+        }
+
+        if !is_valid_name(pn) {
+            return Err(error("invalid package name"));
+        }
+
+        Ok(Atom {
+            operator,
+            category,
+            pn: pn.to_string(),
+            version,
+            glob,
+            slot
+        })
+    }
+
+    // Whether the atom requires the package slot to match.
+    pub fn has_slot(&self) -> bool {
+        self.slot.is_some()
+    }
+
+    // Whether a package matches this atom. The slot is the contents of the
+    // package's SLOT file, including the sub-slot if any.
+    pub fn matches(&self, package: &PackageName, slot: Option<&str>) -> bool {
+        if self.pn != package.pn {
+            return false;
+        }
+
+        if self.category.as_ref().is_some_and(|category| *category != package.category) {
+            return false;
+        }
+
+        if let Some(wanted) = &self.slot {
+            let slot = slot.unwrap_or("0");
+            let matches = if wanted.contains('/') {
+                slot == wanted
+            } else {
+                slot.split('/').next() == Some(wanted.as_str())
+            };
+
+            if !matches {
+                return false;
+            }
+        }
+
+        let (operator, version) = match (self.operator, &self.version) {
+            (Some(operator), Some(version)) => (operator, version),
+            _ => return true
+        };
+
+        let other = &package.version;
+        match operator {
+            Operator::Equal if self.glob => version.is_prefix_of(other),
+            Operator::Equal => other == version,
+            Operator::Approximate => other.cmp_base(version) == Ordering::Equal,
+            Operator::Less => other < version,
+            Operator::LessEqual => other <= version,
+            Operator::GreaterEqual => other >= version,
+            Operator::Greater => other > version
+        }
+    }
+}
+
+impl AtomError {
+    fn new(input: &str, reason: &'static str) -> AtomError {
+        AtomError { input: input.to_string(), reason }
+    }
+}
+
+impl fmt::Display for AtomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.input, self.reason)
+    }
+}
+
+// Split a `package-version` name into the package name and version.
+fn split_version(pf: &str) -> Option<(&str, Version)> {
+    pf.match_indices('-').find_map(|(index, _)| {
+        let version = &pf[index + 1..];
+        if !version.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+
+        Version::parse(version).ok().map(|version| (&pf[..index], version))
+    })
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(['-', '.', '+'])
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "+_.-".contains(c))
+}
+
+fn is_number(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_digit())
+}
+
+// Compare decimal integers of any length.
+fn cmp_integers(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(text: &str) -> Version {
+        Version::parse(text).unwrap()
+    }
+
+    fn matches(atom: &str, pf: &str, slot: Option<&str>) -> bool {
+        let package = PackageName::parse("cat", pf).unwrap();
+        Atom::parse(atom).unwrap().matches(&package, slot)
+    }
+
+    #[test]
+    fn version_ordering() {
+        let ascending = [
+            ("1.0_alpha", "1.0_alpha1"),
+            ("1.0_alpha1", "1.0_alpha2"),
+            ("1.0_alpha2", "1.0_beta"),
+            ("1.0_beta", "1.0_pre"),
+            ("1.0_pre", "1.0_rc1"),
+            ("1.0_rc1", "1.0"),
+            ("1.0", "1.0_p"),
+            ("1.0_p", "1.0_p1"),
+            ("1.0_p1_alpha", "1.0_p1"),
+            ("1.0_p1", "1.0_p1_p1"),
+            ("1.0", "1.0a"),
+            ("1.0a", "1.0b"),
+            ("1.0z", "1.0.1"),
+            ("1.0", "1.0.0"),
+            ("1.2", "1.10"),
+            ("1.9", "1.10"),
+            ("1.01", "1.1"),
+            ("1.001", "1.01"),
+            ("2", "10"),
+            ("1.0", "1.0-r1"),
+            ("1.0-r1", "1.0-r2"),
+            ("1.0-r9", "1.0-r10"),
+            ("1.0-r10", "1.0.1")
+        ];
+
+        for (a, b) in ascending {
+            assert!(version(a) < version(b), "{} < {}", a, b);
+            assert!(version(b) > version(a), "{} > {}", b, a);
+        }
+    }
+
+    #[test]
+    fn version_equality() {
+        let equal = [
+            ("1.0", "1.0-r0"),
+            ("1.0-r01", "1.0-r1"),
+            ("01.0", "1.0"),
+            ("1.010", "1.01"),
+            ("1.0_alpha", "1.0_alpha0"),
+            ("1.0_p", "1.0_p0")
+        ];
+
+        for (a, b) in equal {
+            assert_eq!(version(a), version(b), "{} == {}", a, b);
+        }
+    }
+
+    #[test]
+    fn version_rejected() {
+        for text in ["", "1.", ".1", "1..2", "a", "1.0_foo", "1.0_alpha_", "1.0ab",
+                     "1.0-rx", "1.0-r", "1.0-r1-r1"] {
+            assert!(Version::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn atom_operators() {
+        assert!(matches("cat/pkg", "pkg-1.0", None));
+        assert!(matches("pkg", "pkg-1.0", None));
+        assert!(!matches("other/pkg", "pkg-1.0", None));
+        assert!(!matches("cat/pkg", "pkg-extra-1.0", None));
+
+        assert!(matches("=cat/pkg-1.0", "pkg-1.0-r0", None));
+        assert!(!matches("=cat/pkg-1.0", "pkg-1.0-r1", None));
+        assert!(matches(">=cat/pkg-1.0", "pkg-1.0", None));
+        assert!(matches(">=cat/pkg-1.0", "pkg-2.0", None));
+        assert!(!matches(">=cat/pkg-1.0", "pkg-1.0_rc1", None));
+        assert!(matches(">cat/pkg-1.0", "pkg-1.0-r1", None));
+        assert!(!matches(">cat/pkg-1.0", "pkg-1.0", None));
+        assert!(!matches("<cat/pkg-1.0", "pkg-1.0_p1_alpha", None));
+        assert!(matches("<cat/pkg-1.0", "pkg-0.9", None));
+        assert!(matches("<=cat/pkg-1.0", "pkg-1.0", None));
+        assert!(!matches("<=cat/pkg-1.0", "pkg-1.0-r1", None));
+    }
+
+    #[test]
+    fn atom_approximate() {
+        assert!(matches("~cat/pkg-1.2", "pkg-1.2", None));
+        assert!(matches("~cat/pkg-1.2", "pkg-1.2-r3", None));
+        assert!(!matches("~cat/pkg-1.2", "pkg-1.2.1", None));
+        assert!(!matches("~cat/pkg-1.2", "pkg-1.2_p1", None));
+        assert!(!matches("~cat/pkg-1.2", "pkg-1.3", None));
+    }
+
+    #[test]
+    fn atom_wildcard() {
+        assert!(matches("=cat/pkg-1.2*", "pkg-1.2", None));
+        assert!(matches("=cat/pkg-1.2*", "pkg-1.2-r1", None));
+        assert!(matches("=cat/pkg-1.2*", "pkg-1.2.3", None));
+        assert!(matches("=cat/pkg-1.2*", "pkg-1.2_rc1", None));
+        assert!(matches("=cat/pkg-1.2*", "pkg-1.2a", None));
+        assert!(!matches("=cat/pkg-1.2*", "pkg-1.20", None));
+        assert!(!matches("=cat/pkg-1.2*", "pkg-1.3", None));
+        assert!(!matches("=cat/pkg-1.2*", "pkg-1.1", None));
+    }
+
+    #[test]
+    fn atom_slot() {
+        assert!(matches("cat/pkg:3", "pkg-3.1", Some("3")));
+        assert!(matches("cat/pkg:3", "pkg-3.1", Some("3/3.1")));
+        assert!(!matches("cat/pkg:3", "pkg-4.0", Some("4")));
+        assert!(!matches("cat/pkg:3", "pkg-3.1", Some("30")));
+        assert!(matches("cat/pkg:3/3.1", "pkg-3.1", Some("3/3.1")));
+        assert!(!matches("cat/pkg:3/3.1", "pkg-3.2", Some("3/3.2")));
+        assert!(!matches("cat/pkg:3/3.1", "pkg-3.1", Some("3")));
+        assert!(matches("cat/pkg:0", "pkg-1.0", None));
+        assert!(matches(">=cat/pkg-3:3", "pkg-3.1", Some("3")));
+        assert!(!matches(">=cat/pkg-3:3", "pkg-2.9", Some("3")));
+    }
+
+    #[test]
+    fn atom_rejected() {
+        for text in ["cat/pkg-1.0", "=cat/pkg", ">=cat/pkg-1.0*", "cat/pkg*",
+                     "cat/pkg:", "~cat/pkg-1.0-r1", "=cat/pkg-1.0_foo",
+                     "-cat/pkg", "cat/-pkg", "cat/.pkg", "cat/pk g", "/pkg", "cat/"] {
+            assert!(Atom::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn package_name() {
+        let package = PackageName::parse("dev-lang", "python-3.12.4-r1").unwrap();
+        assert_eq!(package.to_string(), "dev-lang/python-3.12.4-r1");
+        assert_eq!(package.pn, "python");
+        assert!(PackageName::parse("dev-lang", "python").is_err());
+        assert!(PackageName::parse("-dev", "python-3.12").is_err());
+    }
+}
@@ -0,0 +1,163 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::collections::HashMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path,PathBuf};
+use std::sync::{Arc,Mutex,OnceLock};
+use std::{fs,io,mem};
+
+use serde::{Deserialize,Serialize};
+
+use crate::Settings;
+use crate::cache;
+use super::file::{FileType,path_bytes};
+
+const VERSION: u32 = 3;
+
+static INSTANCE: OnceLock<CatalogCache> = OnceLock::new();
+
+// Parsed CONTENTS files saved between runs. The entries of a package are
+// reused while its package database directory, COUNTER and CONTENTS files
+// are unchanged. Paths are cached as recorded, before merged-usr
+// translation.
+pub struct CatalogCache {
+    path: Option<PathBuf>,
+    packages: Mutex<HashMap<String, Cached>>
+}
+
+// A CONTENTS entry as recorded in the package database. An MD5 sum that
+// cannot be parsed is treated as absent.
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct Entry {
+    #[serde(with = "path_bytes")]
+    pub path: PathBuf,
+    pub file_type: FileType,
+    pub md5: Option<[u8; 16]>,
+    pub mtime: u64
+}
+
+// Identifies a version of a package database directory.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct Stamp {
+    dir_mtime_ns: i64,
+    counter: String,
+    contents_ino: u64,
+    contents_size: u64,
+    contents_mtime_ns: i64,
+    contents_ctime_ns: i64
+}
+
+#[derive(Serialize,Deserialize)]
+struct Cached {
+    stamp: Stamp,
+    entries: Arc<[Entry]>,
+    #[serde(skip)]
+    used: bool
+}
+
+impl CatalogCache {
+    pub fn get() -> &'static CatalogCache {
+        INSTANCE.get_or_init(|| {
+            let settings = Settings::get();
+            if !settings.catalog_cache() {
+                return CatalogCache { path: None, packages: Mutex::default() };
+            }
+
+            let path = cache_path(&settings);
+            let packages = cache::load(&path, VERSION);
+
+            CatalogCache { path: Some(path), packages: Mutex::new(packages) }
+        })
+    }
+
+    // The cached entries of a package, if the package has not changed
+    // since they were parsed. The entries are shared with the cache rather
+    // than copied.
+    pub fn lookup(&self, name: &str, stamp: &Stamp) -> Option<Arc<[Entry]>> {
+        self.path.as_ref()?;
+        let mut packages = self.packages.lock().unwrap();
+        let cached = packages.get_mut(name)?;
+        if cached.stamp != *stamp {
+            return None;
+        }
+
+        cached.used = true;
+        Some(cached.entries.clone())
+    }
+
+    pub fn insert(&self, name: &str, stamp: Stamp, entries: Arc<[Entry]>) {
+        if self.path.is_none() {
+            return;
+        }
+
+        let cached = Cached { stamp, entries, used: true };
+        self.packages.lock().unwrap().insert(name.to_string(), cached);
+    }
+
+    // Write the cache to disk and release it, as it is saved once every
+    // package has been read. When `prune` is set, packages that were not
+    // read during this run are removed from the cache.
+    pub fn save(&self, prune: bool) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return
+        };
+
+        let mut packages = mem::take(&mut *self.packages.lock().unwrap());
+        if prune {
+            packages.retain(|_, cached| cached.used);
+        }
+
+        cache::store(path, VERSION, &packages);
+    }
+}
+
+impl Stamp {
+    // Read the stamp of the package database directory containing a
+    // CONTENTS file.
+    pub fn read(contents_path: &Path) -> io::Result<Stamp> {
+        let dir = contents_path.parent().unwrap_or(contents_path);
+        let dir_metadata = fs::metadata(dir)?;
+        let contents_metadata = fs::metadata(contents_path)?;
+        let counter = fs::read_to_string(dir.join("COUNTER")).unwrap_or_default();
+
+        Ok(Stamp {
+            dir_mtime_ns: cache::ns(dir_metadata.mtime(), dir_metadata.mtime_nsec()),
+            counter: counter.trim().to_string(),
+            contents_ino: contents_metadata.ino(),
+            contents_size: contents_metadata.size(),
+            contents_mtime_ns: cache::ns(contents_metadata.mtime(), contents_metadata.mtime_nsec()),
+            contents_ctime_ns: cache::ns(contents_metadata.ctime(), contents_metadata.ctime_nsec())
+        })
+    }
+}
+
+// Each package database has its own cache file, named after the MD5 sum of
+// the database path.
+fn cache_path(settings: &Settings) -> PathBuf {
+    let pkg_dir = settings.pkg_dir();
+    let pkg_dir = fs::canonicalize(&pkg_dir).unwrap_or(pkg_dir);
+    let digest = md5::compute(pkg_dir.as_os_str().as_encoded_bytes());
+    settings.cache_dir().join(format!("catalog-{:x}.cache", digest))
+}
@@ -22,68 +22,114 @@
 //
 
 use std::path::{Path,PathBuf};
-use crate::file_info::FileInfo;
+use std::sync::Arc;
+use serde::{Deserialize,Serialize};
 
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 pub struct File {
     path: PathBuf,
+    record: Record
+}
+
+// A package database entry without its path, for storing by path in a
+// `PathTree`.
+#[derive(Clone,Debug)]
+pub struct Record {
     file_type: FileType,
     mtime: u64,
-    md5: Option<String>
+    md5: Option<md5::Digest>,
+    package: Arc<str>
 }
 
-#[derive(Clone,Debug,Hash,PartialEq)]
+#[derive(Clone,Debug,Hash,PartialEq,Serialize,Deserialize)]
 pub enum FileType {
     Dir,
     Obj,
-    Sym(PathBuf)
+    Sym(#[serde(with = "path_bytes")] PathBuf),
+    Fif,
+    Dev
+}
+
+impl FileType {
+    // The entry type as written in CONTENTS files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileType::Dir => "dir",
+            FileType::Obj => "obj",
+            FileType::Sym(_) => "sym",
+            FileType::Fif => "fif",
+            FileType::Dev => "dev"
+        }
+    }
 }
 
 impl File {
-    pub fn new<P>(path: P, file_type: FileType, md5: String, mtime: u64)
-        -> File where P: AsRef<Path>
+    pub fn new<P>(path: P, file_type: FileType, md5: Option<[u8; 16]>, mtime: u64,
+                  package: Arc<str>) -> File where P: AsRef<Path>
     {
-        let md5 = if md5.is_empty() {
-            None
-        } else {
-            Some(md5)
-        };
-
         File {
             path: path.as_ref().to_path_buf(),
-            file_type: file_type,
-            mtime: mtime,
-            md5: md5
+            record: Record { file_type, mtime, md5: md5.map(md5::Digest), package }
         }
     }
 
-    pub fn to_file_info(self) -> Box<dyn FileInfo> {
-        Box::new(self)
+    pub fn path(&self) -> &Path {
+        &self.path
     }
-}
 
-impl FileInfo for File {
-    fn path(&self) -> &Path {
-        &self.path
+    pub fn file_type(&self) -> &FileType {
+        self.record.file_type()
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.record.mtime()
     }
 
-    fn file_type(&self) -> FileType {
-        self.file_type.clone()
+    pub fn md5(&self) -> Option<md5::Digest> {
+        self.record.md5()
     }
 
-    fn mtime(&self) -> u64 {
+    pub fn package(&self) -> &str {
+        self.record.package()
+    }
+
+    pub fn into_record(self) -> (PathBuf, Record) {
+        (self.path, self.record)
+    }
+}
+
+impl Record {
+    pub fn file_type(&self) -> &FileType {
+        &self.file_type
+    }
+
+    pub fn mtime(&self) -> u64 {
         self.mtime
     }
 
-    fn md5(&self) -> Option<&str> {
-        self.md5.as_deref()
+    pub fn md5(&self) -> Option<md5::Digest> {
+        self.md5
+    }
+
+    pub fn package(&self) -> &str {
+        &self.package
     }
+}
+
+// Serializes paths as their raw bytes, since names are not necessarily
+// valid UTF-8.
+pub mod path_bytes {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::{OsStrExt,OsStringExt};
+    use std::path::{Path,PathBuf};
+    use serde::{Deserialize,Deserializer,Serialize,Serializer};
 
-    fn md5_matches(&self, other: &dyn FileInfo) -> bool {
-        other.md5_matches(self)
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok,S::Error> {
+        path.as_os_str().as_bytes().serialize(serializer)
     }
 
-    fn mtime_matches(&self, other: &dyn FileInfo) -> bool {
-        other.mtime_matches(self)
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf,D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        Ok(PathBuf::from(OsString::from_vec(bytes)))
     }
 }
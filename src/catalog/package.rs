@@ -21,94 +21,385 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
 
+use std::ffi::OsStr;
 use std::io::{BufRead,BufReader};
-use std::io::Lines;
+use std::io::Split;
+use std::iter::Enumerate;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path,PathBuf};
-use std::fs;
+use std::sync::{Arc,Mutex};
+use std::{fmt,fs};
 
 use crate::Settings;
+use crate::escape::escape;
+use super::cache::{CatalogCache,Entry,Stamp};
 use super::file::{File,FileType};
 
-pub struct Package<'a> {
-    lines: Lines<BufReader<fs::File>>,
-    settings: &'a Settings
+// Packages whose CONTENTS files could not be read completely.
+static DAMAGED: Mutex<Vec<Damaged>> = Mutex::new(Vec::new());
+
+pub struct Package {
+    path: PathBuf,
+    lines: Enumerate<Split<BufReader<fs::File>>>,
+    failed: bool
+}
+
+// An error reading a CONTENTS file, at `line` if the file could be opened.
+#[derive(Clone,Debug)]
+pub struct ParseError {
+    path: PathBuf,
+    line: Option<usize>,
+    reason: String
+}
+
+// A package whose CONTENTS file could not be read completely, with the
+// number of lines skipped and the first error.
+#[derive(Clone,Debug)]
+pub struct Damaged {
+    pub package: Arc<str>,
+    pub skipped: usize,
+    pub error: ParseError
 }
 
-impl<'a> Package<'a> {
-    pub fn read<P>(contents_path: P, settings: &'a Settings) -> Vec<File>
+impl Package {
+    // Read the entries of a package, from the catalog cache if the package
+    // has not changed since it was last parsed. Lines that cannot be
+    // parsed are skipped, or returned as an error in strict mode.
+    pub fn read<P>(contents_path: P, settings: &Settings) -> Result<Vec<File>,ParseError>
         where P: AsRef<Path>
     {
-        let file = fs::File::open(contents_path.as_ref()).unwrap();
-        let reader = BufReader::new(file);
-        let lines = reader.lines();
+        let contents_path = contents_path.as_ref();
+        let name: Arc<str> = package_name(contents_path).into();
+        let cache = CatalogCache::get();
+        let stamp = Stamp::read(contents_path).ok();
+        let cached = stamp.as_ref().and_then(|stamp| cache.lookup(&name, stamp));
+        let entries = match cached {
+            Some(entries) => entries,
+            None => {
+                let (entries, damaged) = Self::parse(contents_path, &name, settings)?;
+                let entries: Arc<[Entry]> = entries.into();
+                match damaged {
+                    // Damaged packages are not cached, so that they are
+                    // reported again on every run until repaired.
+                    Some(damaged) => DAMAGED.lock().unwrap().push(damaged),
+                    None => {
+                        if let Some(stamp) = stamp {
+                            cache.insert(&name, stamp, entries.clone());
+                        }
+                    }
+                }
+                entries
+            }
+        };
 
-        Package { lines, settings }.collect()
+        Ok(entries.iter().map(|entry| {
+            let path = settings.usr_path(&entry.path);
+            File::new(path, entry.file_type.clone(), entry.md5, entry.mtime, name.clone())
+        }).collect())
+    }
+
+    // The packages whose CONTENTS files could not be read completely,
+    // sorted by name.
+    pub fn damaged() -> Vec<Damaged> {
+        let mut damaged = DAMAGED.lock().unwrap().clone();
+        damaged.sort_by(|a, b| a.package.cmp(&b.package));
+        damaged
+    }
+
+    // Parse a CONTENTS file, returning its entries and, if any lines were
+    // skipped, the damage. A file that cannot be opened is skipped entirely
+    // unless in strict mode.
+    fn parse(contents_path: &Path, name: &Arc<str>, settings: &Settings)
+        -> Result<(Vec<Entry>, Option<Damaged>),ParseError>
+    {
+        let file = match fs::File::open(contents_path) {
+            Ok(file) => file,
+            Err(err) => {
+                let error = ParseError::new(contents_path, None, err.to_string());
+                if settings.strict() {
+                    return Err(error);
+                }
+
+                let damaged = Damaged { package: name.clone(), skipped: 0, error };
+                return Ok((Vec::new(), Some(damaged)));
+            }
+        };
+
+        let package = Package {
+            path: contents_path.to_path_buf(),
+            lines: BufReader::new(file).split(b'\n').enumerate(),
+            failed: false
+        };
+
+        let mut entries = Vec::new();
+        let mut damaged: Option<Damaged> = None;
+        for result in package {
+            match result {
+                Ok(entry) => entries.push(entry),
+                Err(err) if settings.strict() => return Err(err),
+                Err(err) => {
+                    if settings.verbose() {
+                        eprintln!("Skipping {}", err);
+                    }
+
+                    match &mut damaged {
+                        Some(damaged) => damaged.skipped += 1,
+                        None => {
+                            damaged = Some(Damaged { package: name.clone(), skipped: 1, error: err });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok((entries, damaged))
+    }
+}
+
+impl ParseError {
+    fn new(path: &Path, line: Option<usize>, reason: String) -> ParseError {
+        ParseError { path: path.to_path_buf(), line, reason }
     }
 }
 
-impl Iterator for Package<'_> {
-    type Item = File;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.reason),
+            None => write!(f, "{}: {}", self.path.display(), self.reason)
+        }
+    }
+}
 
+// The `category/package-version` name of the package whose CONTENTS file
+// is at `contents_path`.
+fn package_name(contents_path: &Path) -> String {
+    let dir = contents_path.parent().unwrap_or(contents_path);
+    let pf = dir.file_name().unwrap_or_default();
+    let category = dir.parent()
+        .and_then(|parent| parent.file_name())
+        .unwrap_or_default();
+
+    format!("{}/{}", category.to_string_lossy(), pf.to_string_lossy())
+}
+
+impl Iterator for Package {
+    type Item = Result<Entry,ParseError>;
+
+    // Line numbers start at 1. Reading stops after an I/O error.
     fn next(&mut self) -> Option<Self::Item> {
-        match self.lines.next() {
-            Some(line) => Some(parse_entry(&line.unwrap(), &self.settings)),
-            None => None
+        if self.failed {
+            return None;
         }
+
+        let (index, line) = self.lines.next()?;
+        let result = match line {
+            Ok(line) => parse_entry(&line),
+            Err(err) => {
+                self.failed = true;
+                Err(err.to_string())
+            }
+        };
+
+        Some(result.map_err(|reason| ParseError::new(&self.path, Some(index + 1), reason)))
     }
 }
 
-fn parse_entry(line: &str, settings: &Settings) -> File {
-    let path: String;
+// Parse a line of a CONTENTS file as raw bytes. The fields following the
+// path are split from the right, so that a path may contain any byte but
+// a newline. As in Portage, a `sym` entry is split at its last ` -> `, so
+// the link may contain ` -> ` but its target may not.
+fn parse_entry(line: &[u8]) -> Result<Entry,String> {
+    let path: &[u8];
     let file_type: FileType;
-    let md5: String;
+    let md5: Option<[u8; 16]>;
     let mtime: u64;
 
-    let fields = line.split(' ').collect::<Vec<_>>();
-    match fields[0] {
-        "obj" => {
-            let len = fields.len();
-            path = fields[1..=(len - 3)].join(" ");
+    let (kind, rest) = match line.iter().position(|byte| *byte == b' ') {
+        Some(index) => (&line[..index], &line[index + 1..]),
+        None => (line, &line[line.len()..])
+    };
+
+    match kind {
+        b"obj" => {
+            let (rest, field) = rsplit_field(rest).ok_or("obj entry is missing fields")?;
+            let (source, sum) = rsplit_field(rest).ok_or("obj entry is missing fields")?;
+            path = source;
             file_type = FileType::Obj;
-            md5 = String::from(fields[len - 2]);
-            mtime = fields[len - 1].parse().unwrap();
+            md5 = parse_md5(sum);
+            mtime = parse_mtime(field)?;
         },
 
-        "dir" => {
-            let len = fields.len();
-            path = fields[1..=(len - 1)].join(" ");
+        b"dir" => {
+            path = rest;
             file_type = FileType::Dir;
-            md5 = String::from("");
+            md5 = None;
+            mtime = 0;
+        },
+
+        b"sym" => {
+            let (link, field) = rsplit_field(rest).ok_or("sym entry is missing fields")?;
+            let index = link.windows(4)
+                .rposition(|window| window == b" -> ")
+                .ok_or("sym entry is missing ` -> `")?;
+            let target = &link[index + 4..];
+            if target.is_empty() {
+                return Err(String::from("sym entry is missing a target"));
+            }
+
+            path = &link[..index];
+            file_type = FileType::Sym(PathBuf::from(OsStr::from_bytes(target)));
+            md5 = None;
+            mtime = parse_mtime(field)?;
+        },
+
+        b"fif" => {
+            path = rest;
+            file_type = FileType::Fif;
+            md5 = None;
             mtime = 0;
         },
 
-        "sym" => {
-            let split = line.split(" -> ").collect::<Vec<_>>();
-            let len = split[0].len();
-            path = split[0][4..=(len - 1)].to_string();
-            let split = split[1].split(" ").collect::<Vec<_>>();
-            let len = split.len();
-            let target = split[0..=(len - 1)][0].to_string();
-            file_type = FileType::Sym(PathBuf::from(target));
-            md5 = String::from("");
-            let len = fields.len();
-            mtime = fields[len - 1].parse().unwrap();
+        b"dev" => {
+            path = rest;
+            file_type = FileType::Dev;
+            md5 = None;
+            mtime = 0;
         },
 
-        _ => panic!("Unrecognized file type!")
+        other => {
+            return Err(format!("unrecognized entry type `{}`", escape(OsStr::from_bytes(other))));
+        }
     };
 
-    let mut path = PathBuf::from(path);
-    if !settings.is_split_usr() {
-        if let Some(links) = settings.links_to_usr() {
-            for link in links {
-                if path.starts_with(link.src()) {
-                    let count = link.src().components().count();
-                    let pathbuf: PathBuf = path.components().skip(count).collect();
-                    path = PathBuf::from(link.dst()).join(pathbuf);
-                }
-            }
+    if path.is_empty() {
+        return Err(format!("{} entry is missing a path", file_type.name()));
+    }
+
+    Ok(Entry { path: PathBuf::from(OsStr::from_bytes(path)), file_type, md5, mtime })
+}
+
+// Split the last space-separated field from the end of `bytes`.
+fn rsplit_field(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let index = bytes.iter().rposition(|byte| *byte == b' ')?;
+    Some((&bytes[..index], &bytes[index + 1..]))
+}
+
+// Parse an MD5 sum written in hexadecimal. Sums that cannot be parsed are
+// treated as absent, and so never match the sum of a file.
+fn parse_md5(field: &[u8]) -> Option<[u8; 16]> {
+    if field.len() != 32 || !field.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+
+    let mut digest = [0; 16];
+    for (byte, hex) in digest.iter_mut().zip(field.chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+    }
+
+    Some(digest)
+}
+
+fn parse_mtime(field: &[u8]) -> Result<u64,String> {
+    std::str::from_utf8(field).ok()
+        .and_then(|field| field.parse().ok())
+        .ok_or_else(|| format!("invalid modification time `{}`", escape(OsStr::from_bytes(field))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(entry: &Entry) -> &[u8] {
+        entry.path.as_os_str().as_bytes()
+    }
+
+    fn target(entry: &Entry) -> &[u8] {
+        match &entry.file_type {
+            FileType::Sym(target) => target.as_os_str().as_bytes(),
+            _ => panic!("not a sym entry")
         }
     }
 
-    File::new(path, file_type, md5, mtime)
+    #[test]
+    fn obj_entry() {
+        let entry = parse_entry(b"obj /usr/bin/foo d41d8cd98f00b204e9800998ecf8427e 1700000000").unwrap();
+        assert_eq!(path(&entry), b"/usr/bin/foo");
+        assert_eq!(entry.file_type, FileType::Obj);
+        assert_eq!(entry.md5, Some([0xd4, 0x1d, 0x8c, 0xd9, 0x8f, 0x00, 0xb2, 0x04,
+                                    0xe9, 0x80, 0x09, 0x98, 0xec, 0xf8, 0x42, 0x7e]));
+        assert_eq!(entry.mtime, 1700000000);
+        let entry = parse_entry(b"obj /usr/bin/foo +41d8cd98f00b204e9800998ecf8427e 1").unwrap();
+        assert_eq!(entry.md5, None);
+        let entry = parse_entry(b"obj /usr/bin/foo d41d8cd9 1").unwrap();
+        assert_eq!(entry.md5, None);
+        let entry = parse_entry(b"fif /run/foo/fifo").unwrap();
+        assert_eq!(path(&entry), b"/run/foo/fifo");
+        assert_eq!(entry.file_type, FileType::Fif);
+        let entry = parse_entry(b"dev /lib/foo/null").unwrap();
+        assert_eq!(path(&entry), b"/lib/foo/null");
+        assert_eq!(entry.file_type, FileType::Dev);
+    }
+
+    #[test]
+    fn consecutive_spaces() {
+        let entry = parse_entry(b"obj /opt/a  b  d41d8cd98f00b204e9800998ecf8427e 1").unwrap();
+        assert_eq!(path(&entry), b"/opt/a  b ");
+        let entry = parse_entry(b"dir /opt/a  b").unwrap();
+        assert_eq!(path(&entry), b"/opt/a  b");
+        let entry = parse_entry(b"sym /opt/a  b -> c  d 1").unwrap();
+        assert_eq!(path(&entry), b"/opt/a  b");
+        assert_eq!(target(&entry), b"c  d");
+    }
+
+    #[test]
+    fn arrow_in_path() {
+        let entry = parse_entry(b"obj /opt/x -> y d41d8cd98f00b204e9800998ecf8427e 1").unwrap();
+        assert_eq!(path(&entry), b"/opt/x -> y");
+        let entry = parse_entry(b"sym /opt/x -> y -> z 1").unwrap();
+        assert_eq!(path(&entry), b"/opt/x -> y");
+        assert_eq!(target(&entry), b"z");
+    }
+
+    #[test]
+    fn spaces_in_target() {
+        let entry = parse_entry(b"sym /usr/lib/link -> target with spaces 1700000000").unwrap();
+        assert_eq!(path(&entry), b"/usr/lib/link");
+        assert_eq!(target(&entry), b"target with spaces");
+        assert_eq!(entry.mtime, 1700000000);
+    }
+
+    #[test]
+    fn non_utf8() {
+        let entry = parse_entry(b"obj /opt/bad\xffname d41d8cd98f00b204e9800998ecf8427e 1").unwrap();
+        assert_eq!(path(&entry), b"/opt/bad\xffname");
+        let entry = parse_entry(b"sym /opt/l\xfe -> t\xfd 1").unwrap();
+        assert_eq!(path(&entry), b"/opt/l\xfe");
+        assert_eq!(target(&entry), b"t\xfd");
+        let entry = parse_entry(b"dir /opt/\xc3").unwrap();
+        assert_eq!(path(&entry), b"/opt/\xc3");
+    }
+
+    #[test]
+    fn empty_path_or_target() {
+        assert!(parse_entry(b"dir").is_err());
+        assert!(parse_entry(b"dir ").is_err());
+        assert!(parse_entry(b"obj  d41d8cd98f00b204e9800998ecf8427e 1").is_err());
+        assert!(parse_entry(b"sym  -> target 1").is_err());
+        assert!(parse_entry(b"sym /opt/link ->  1").is_err());
+        assert!(parse_entry(b"sym /opt/link -> 1").is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(parse_entry(b"").is_err());
+        assert!(parse_entry(b"bogus /opt/foo").is_err());
+        assert!(parse_entry(b"fifo /opt/foo").is_err());
+        assert!(parse_entry(b"fif").is_err());
+        assert!(parse_entry(b"dev ").is_err());
+        assert!(parse_entry(b"obj /opt/foo").is_err());
+        assert!(parse_entry(b"obj /opt/foo d41d8cd98f00b204e9800998ecf8427e x").is_err());
+        assert!(parse_entry(b"sym /opt/link target 1").is_err());
+        assert!(parse_entry(b"sym /opt/link -> target").is_err());
+    }
 }
@@ -21,33 +21,33 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
 
-use std::any::Any;
-use std::hash::{Hash,Hasher};
-use std::path::Path;
+pub mod check;
+pub mod collisions;
+pub mod owner;
 
-use crate::catalog::file::FileType;
+use std::io;
+use std::path::PathBuf;
 
-pub trait FileInfo: Any {
-    fn path(&self) -> &Path;
-    fn file_type(&self) -> FileType;
-    fn mtime(&self) -> u64;
-    fn md5(&self) -> Option<&str>;
-    fn md5_matches(&self, other: &dyn FileInfo) -> bool;
-    fn mtime_matches(&self, other: &dyn FileInfo) -> bool;
+// The subcommand specified on the command line. Without a subcommand the
+// file system is scanned for files not recorded in the package database.
+#[derive(Clone,Debug,Default)]
+pub enum Command {
+    #[default]
+    Scan,
+    Check(Vec<String>),
+    Collisions,
+    Owner(Vec<PathBuf>)
 }
 
-impl Eq for dyn FileInfo { }
+// Whether writing the output of a command failed, reporting the error. The
+// output being closed early, such as by `head`, is not a failure.
+pub fn output_failed(result: io::Result<()>) -> bool {
+    match result {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+            eprintln!("Error writing output: {}", err);
+            true
+        },
 
-impl PartialEq for dyn FileInfo {
-    fn eq(&self, other: &dyn FileInfo) -> bool {
-        self.path() == other.path() &&
-            self.file_type() == other.file_type()
-    }
-}
-
-impl Hash for dyn FileInfo {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.path().hash(state);
-        self.file_type().hash(state);
+        _ => false
     }
 }
@@ -0,0 +1,181 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::io::{self,Write};
+use std::process;
+
+use crate::Settings;
+use crate::catalog::Catalog;
+use crate::catalog::atom::Atom;
+use crate::catalog::cache::CatalogCache;
+use crate::catalog::file::{File as PkgFile,FileType};
+use crate::command;
+use crate::escape::escape;
+use crate::file_system;
+use crate::file_system::File;
+use crate::file_system::file::Stat;
+use crate::file_system::md5_cache::Md5Cache;
+
+#[derive(Default)]
+struct Summary {
+    files: usize,
+    modified: usize,
+    missing: usize,
+    unreadable: usize
+}
+
+// Verify the files recorded in the CONTENTS of each matching package, and
+// display the files that are missing or modified followed by a summary for
+// each package. Exits with a non-zero status if any package has missing,
+// modified or unreadable files, if its CONTENTS file is damaged, or if an
+// atom does not match an installed package.
+pub fn run(atoms: &[String]) {
+    let mut failed = false;
+    let result = check_atoms(atoms, &mut failed);
+    CatalogCache::get().save(false);
+    Md5Cache::get().save(false);
+    failed |= Catalog::report_damaged();
+    failed |= command::output_failed(result);
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn check_atoms(atoms: &[String], failed: &mut bool) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    for text in atoms {
+        let atom = match Atom::parse(text) {
+            Ok(atom) => atom,
+            Err(err) => {
+                eprintln!("Invalid atom {}", err);
+                *failed = true;
+                continue;
+            }
+        };
+
+        let packages = Catalog::find_packages(&atom);
+        if packages.is_empty() {
+            eprintln!("{}: no installed package matches", text);
+            *failed = true;
+            continue;
+        }
+
+        for (name, contents) in packages {
+            let entries = match Catalog::read_package(contents) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    eprintln!("Error reading {}: {}", name, err);
+                    *failed = true;
+                    continue;
+                }
+            };
+
+            let summary = check_package(&entries, &mut out)?;
+            write!(out, "{}: {} files, {} modified, {} missing", name,
+                   summary.files, summary.modified, summary.missing)?;
+            if summary.unreadable > 0 {
+                write!(out, ", {} unreadable", summary.unreadable)?;
+            }
+            writeln!(out)?;
+            *failed |= summary.modified > 0 || summary.missing > 0 || summary.unreadable > 0;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_package(entries: &[PkgFile], out: &mut impl Write) -> io::Result<Summary> {
+    let settings = Settings::get();
+    let mut summary = Summary::default();
+    for entry in entries {
+        summary.files += 1;
+        let path = settings.root_path(entry.path());
+        let file = match file_system::stat(&path, &settings) {
+            Ok(file) => file,
+            Err(err) if file_system::is_missing(&err) => {
+                summary.missing += 1;
+                writeln!(out, "{}", File::new(entry.path(), Stat::Missing, 0))?;
+                continue;
+            },
+
+            Err(err) => {
+                summary.unreadable += 1;
+                eprintln!("Error reading {}: {}", path.display(), err);
+                continue;
+            }
+        };
+
+        match verify(file, entry) {
+            Ok(Some(file)) => {
+                summary.modified += 1;
+                writeln!(out, "{}", file)?;
+            },
+
+            Ok(None) => (),
+            Err(err) => {
+                summary.unreadable += 1;
+                eprintln!("Error reading {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+// Returns the file if it does not match the package database entry.
+fn verify(file: File, entry: &PkgFile) -> io::Result<Option<File>> {
+    let stat = file.stat();
+    if !stat.is_type(entry.file_type()) {
+        let note = format!("expected {}, found {}",
+                           entry.file_type().name(), stat.type_name());
+        let file = file.with_note(note);
+        file.set_stat(Stat::TypeMismatch);
+        return Ok(Some(file));
+    }
+
+    match (entry.file_type(), &stat) {
+        (FileType::Sym(expected), Stat::Symlink(target)) => {
+            if expected == target {
+                return Ok(None);
+            }
+
+            let note = format!("expected {}, found {}",
+                               escape(expected), escape(target));
+            let file = file.with_note(note);
+            file.set_stat(Stat::Relinked);
+            Ok(Some(file))
+        },
+
+        (FileType::Obj, _) => {
+            let settings = Settings::get();
+            let file = file.calc_md5(settings.root_path(entry.path()))?;
+            if !file.md5_matches(entry.md5()) || !file.mtime_matches(entry.mtime()) {
+                return Ok(Some(file));
+            }
+
+            Ok(None)
+        },
+
+        _ => Ok(None)
+    }
+}
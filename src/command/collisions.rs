@@ -0,0 +1,96 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::collections::HashMap;
+use std::io::{self,Write};
+use std::path::Path;
+use std::process;
+
+use crate::catalog::Catalog;
+use crate::catalog::file::{File,FileType};
+use crate::command;
+use crate::escape::escape;
+
+// Display every `obj` and `sym` path recorded by more than one package,
+// along with the packages and whether their recorded contents agree.
+pub fn run() {
+    let entries = Catalog::read_entries();
+    let mut paths: HashMap<&Path, Vec<&File>> = HashMap::new();
+    for entry in &entries {
+        if matches!(entry.file_type(), FileType::Obj | FileType::Sym(_)) {
+            paths.entry(entry.path()).or_default().push(entry);
+        }
+    }
+
+    let mut collisions: Vec<(&Path, Vec<&File>)> = paths.into_iter()
+        .filter(|(_, files)| {
+            files.iter().any(|file| file.package() != files[0].package())
+        }).collect();
+    collisions.sort_by(|a, b| a.0.cmp(b.0));
+
+    let result = print_collisions(collisions);
+    Catalog::report_damaged();
+    if command::output_failed(result) {
+        process::exit(1);
+    }
+}
+
+fn print_collisions(collisions: Vec<(&Path, Vec<&File>)>) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    for (path, mut files) in collisions {
+        files.sort_by(|a, b| a.package().cmp(b.package()));
+        let packages: Vec<&str> = files.iter()
+            .map(|file| file.package())
+            .collect();
+
+        writeln!(out, "{}: {} [{}]", escape(path), packages.join(" "),
+                 agreement(&files))?;
+    }
+
+    Ok(())
+}
+
+fn agreement(files: &[&File]) -> &'static str {
+    let first = files[0];
+    if files.iter().any(|file| file.file_type().name() != first.file_type().name()) {
+        return "type differs";
+    }
+
+    match first.file_type() {
+        FileType::Sym(_) => {
+            if files.iter().all(|file| file.file_type() == first.file_type()) {
+                "target matches"
+            } else {
+                "target differs"
+            }
+        },
+
+        _ => {
+            if files.iter().all(|file| file.md5() == first.md5()) {
+                "md5 matches"
+            } else {
+                "md5 differs"
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::collections::{BTreeSet,HashMap};
+use std::io::{self,Write};
+use std::path::{Path,PathBuf};
+use std::process;
+
+use crate::Settings;
+use crate::catalog::Catalog;
+use crate::command;
+use crate::escape::escape;
+
+// Display the packages that own each path. For a path that is not owned,
+// the nearest parent directory that is owned is displayed instead. Exits
+// with a non-zero status if any path is not owned.
+pub fn run(paths: &[PathBuf]) {
+    let entries = Catalog::read_entries();
+    let mut owners: HashMap<&Path, BTreeSet<&str>> = HashMap::new();
+    for entry in &entries {
+        owners.entry(entry.path()).or_default().insert(entry.package());
+    }
+
+    let mut unowned = false;
+    let result = print_owners(paths, &owners, &mut unowned);
+    Catalog::report_damaged();
+    if command::output_failed(result) || unowned {
+        process::exit(1);
+    }
+}
+
+fn print_owners(paths: &[PathBuf], owners: &HashMap<&Path, BTreeSet<&str>>,
+                unowned: &mut bool) -> io::Result<()>
+{
+    let settings = Settings::get();
+    let mut out = io::stdout().lock();
+    for value in paths {
+        let path = settings.arg_path(value);
+        if let Some(packages) = owners.get(path.as_path()) {
+            writeln!(out, "{}: {}", escape(value), join(packages))?;
+            continue;
+        }
+
+        *unowned = true;
+        let ancestor = path.ancestors().skip(1)
+            .find_map(|dir| owners.get_key_value(dir));
+
+        match ancestor {
+            Some((dir, packages)) => {
+                writeln!(out, "{}: not owned (nearest owned directory {}: {})",
+                         escape(value), escape(dir), join(packages))?;
+            },
+
+            None => writeln!(out, "{}: not owned", escape(value))?
+        }
+    }
+
+    Ok(())
+}
+
+fn join(packages: &BTreeSet<&str>) -> String {
+    packages.iter().copied().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,89 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+
+// Displays a path so that every name can be told apart. Printable
+// characters are displayed as they are, while backslashes, control
+// characters such as newlines, and bytes that are not valid UTF-8 are
+// escaped, e.g. `\\`, `\n`, `\u{1b}` and `\xff`.
+pub struct Escape<'a>(&'a [u8]);
+
+pub fn escape<S: AsRef<OsStr> + ?Sized>(name: &S) -> Escape<'_> {
+    Escape(name.as_ref().as_bytes())
+}
+
+impl fmt::Display for Escape<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.0.utf8_chunks() {
+            for ch in chunk.valid().chars() {
+                if ch == '\\' || ch.is_control() {
+                    write!(f, "{}", ch.escape_default())?;
+                } else {
+                    write!(f, "{}", ch)?;
+                }
+            }
+
+            for byte in chunk.invalid() {
+                write!(f, "\\x{:02x}", byte)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use super::escape;
+
+    fn escaped(bytes: &[u8]) -> String {
+        escape(OsStr::from_bytes(bytes)).to_string()
+    }
+
+    #[test]
+    fn printable() {
+        assert_eq!(escaped(b"/usr/bin/foo bar"), "/usr/bin/foo bar");
+        assert_eq!(escaped("/opt/caf\u{e9}".as_bytes()), "/opt/caf\u{e9}");
+    }
+
+    #[test]
+    fn escaped_bytes() {
+        assert_eq!(escaped(b"/opt/a\nb"), "/opt/a\\nb");
+        assert_eq!(escaped(b"/opt/a\tb"), "/opt/a\\tb");
+        assert_eq!(escaped(b"/opt/a\\nb"), "/opt/a\\\\nb");
+        assert_eq!(escaped(b"/opt/a\xffb"), "/opt/a\\xffb");
+        assert_eq!(escaped(b"/opt/\x1b"), "/opt/\\u{1b}");
+        assert_eq!(escaped("/opt/\u{85}".as_bytes()), "/opt/\\u{85}");
+    }
+
+    #[test]
+    fn unambiguous() {
+        assert_ne!(escaped(b"a\n"), escaped(b"a\\n"));
+        assert_ne!(escaped(b"a\xff"), escaped(b"a\\xff"));
+    }
+}
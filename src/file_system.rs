@@ -22,21 +22,27 @@
 //
 
 pub mod file;
+pub mod md5_cache;
+mod cruft_ignore;
+mod dir_cache;
+mod mount;
+pub mod resolve;
+mod walk;
 
-use std::collections::HashSet;
 use std::os::unix::fs::FileTypeExt;
 use std::os::unix::fs::MetadataExt;
-use std::path::{Path,PathBuf};
-use std::sync::{Arc,Mutex};
+use std::path::Path;
 use std::time::SystemTime;
 use std::{fs,io};
 
-use walkdir::{DirEntry,WalkDir};
-
-use crate::FileInfo;
 use crate::Settings;
+use self::cruft_ignore::CruftIgnore;
+use self::dir_cache::DirCache;
 use self::file::Stat;
+use self::mount::{Mounts,Visited};
+use self::walk::Filter;
 pub use self::file::File;
+pub use self::walk::{Entry,Visitor};
 
 macro_rules! systime_to_unix {
     ($time:expr) => {
@@ -50,87 +56,133 @@ macro_rules! systime_to_unix {
 pub struct FileSystem;
 
 impl FileSystem {
-    pub fn read() -> HashSet<Box<dyn FileInfo>> {
+    // Traverse every scan path in order, passing the entries found to the
+    // visitor. Scan paths beneath another scan path are traversed only
+    // once. The traversal stops at an error from the visitor, in which case
+    // the directory cache is saved without pruning unvisited directories.
+    pub fn walk<V: Visitor>(visitor: &V) -> io::Result<()> {
         let settings = Settings::get();
-        let walkdir = WalkDir::new("/");
-        let walkdir = walkdir.into_iter()
-            .filter_entry(|e| {
-                is_ignored(e, &settings)
-            });
-
-        let vec = Arc::new(Mutex::new(Vec::new()));
-        let pool = threadpool::Builder::new().build();
-        for result in walkdir {
-            match result {
-                Ok(entry) => {
-                    let path = entry.path().to_path_buf();
-                    let settings = Settings::get();
-                    let vec = vec.clone();
-
-                    pool.execute(move || {
-                        match stat(path, &settings) {
-                            Ok(file) => {
-                                if !ignore_file(file.path(), &settings) {
-                                    let mut vec = vec.lock().unwrap();
-                                    vec.push(file);
-                                }
-                            },
-
-                            Err(_) => { }
-                        }
-                    });
-                },
-
-                Err(err) => {
-                    if settings.verbose() {
-                        eprintln!("Error accessing path: {}", err);
-                    }
-                }
+        let eroot = settings.eroot();
+        let mut paths = settings.scan_paths();
+        paths.sort();
+        paths.dedup_by(|path, prev| path.starts_with(prev));
+        let visited = Visited::new(&paths);
+
+        for path in paths {
+            let cruft_ignore = CruftIgnore::load_parents(&path, &eroot, &settings);
+            let filter = ScanFilter {
+                settings: &settings,
+                mounts: Mounts::new(&path, &visited)
+            };
+            if let Err(err) = walk::walk(path, &cruft_ignore, &filter, visitor) {
+                DirCache::get().save(false);
+                return Err(err);
             }
         }
 
-        pool.join();
-        Arc::try_unwrap(vec).unwrap()
-            .into_inner().unwrap()
-            .into_iter()
-            .map(|file| file.to_file_info())
-            .collect()
+        DirCache::get().save(settings.in_scope(Path::new("/")));
+        Ok(())
+    }
+}
+
+// Skips ignored paths, mount points of skipped file system types, and
+// entries ignored by `.cruftignore` files.
+struct ScanFilter<'a> {
+    settings: &'a Settings,
+    mounts: Mounts<'a>
+}
+
+impl Filter for ScanFilter<'_> {
+    type State = CruftIgnore;
+
+    fn accept(&self, entry: &Entry, cruft_ignore: &CruftIgnore) -> bool {
+        is_ignored(entry, self.settings) &&
+            self.mounts.accept(entry, self.settings) &&
+            cruft_ignore.accept(entry)
+    }
+
+    fn enter(&self, dir: &Entry, cruft_ignore: &CruftIgnore) -> CruftIgnore {
+        cruft_ignore.enter(dir.path(), self.settings)
+    }
+
+    // Entries found beneath a directory that was later replaced by a
+    // smaller path to the same directory are discarded.
+    fn discard(&self, path: &Path) -> bool {
+        self.mounts.is_replaced(path)
+    }
+}
+
+// Whether a path recorded in the package database lies within the parts
+// of the file system that are not scanned.
+pub fn is_path_ignored(path: &Path, settings: &Settings) -> bool {
+    if ignore_file(path, settings) {
+        return true;
     }
+
+    path.ancestors().skip(1).any(|dir| {
+        if let Some(ignore_paths) = settings.ignore_paths() {
+            if ignore_paths.iter().any(|e| e == dir) {
+                return true;
+            }
+        }
+
+        settings.matches_ignore_pattern(dir)
+    })
 }
 
 fn ignore_file(path: &Path, settings: &Settings) -> bool {
     if let Some(ignore_files) = settings.ignore_files() {
-        return ignore_files.iter().any(|e| e == path);
+        if ignore_files.iter().any(|e| e == path) {
+            return true;
+        }
     }
 
-    false
+    settings.matches_ignore_pattern(path)
 }
 
-fn is_ignored(entry: &DirEntry, settings: &Settings) -> bool {
+fn is_ignored(entry: &Entry, settings: &Settings) -> bool {
     let mut rv = true;
-    if !entry.file_type().is_dir() {
+    let path = settings.db_path(entry.path());
+    if !entry.is_dir() {
         if let Some(ignore_files) = settings.ignore_files() {
-            rv &= !ignore_files.iter().any(|e| e == entry.path());
-        }
-    } else {
-        if let Some(ignore_paths) = settings.ignore_paths() {
-            rv &= !ignore_paths.iter().any(|e| e == entry.path());
+            rv &= !ignore_files.contains(&path);
         }
+    } else if let Some(ignore_paths) = settings.ignore_paths() {
+        rv &= !ignore_paths.contains(&path);
     }
 
+    rv &= !settings.matches_ignore_pattern(&path);
     rv
 }
 
-fn stat(path: PathBuf, settings: &Settings) -> io::Result<File>
+// Read the file information for an entry found while traversing the file
+// system. Ignored files and entries that cannot be read are skipped.
+pub fn read_entry(entry: &Entry, settings: &Settings) -> Option<File> {
+    let file = stat(entry.path(), settings).ok()?;
+    if ignore_file(file.path(), settings) {
+        return None;
+    }
+
+    Some(file)
+}
+
+// Whether an error reading a path means that the path does not exist,
+// including when a parent directory has been replaced by a file.
+pub fn is_missing(err: &io::Error) -> bool {
+    matches!(err.kind(), io::ErrorKind::NotFound | io::ErrorKind::NotADirectory)
+}
+
+// Read the file information for a path beneath the root directory. MD5
+// sums are not calculated.
+pub fn stat(path: &Path, settings: &Settings) -> io::Result<File>
 {
     let stat: Stat;
-    let mtime: u64;
 
-    let metadata = fs::symlink_metadata(&path)?;
-    mtime = systime_to_unix!(metadata.modified());
+    let metadata = fs::symlink_metadata(path)?;
+    let mtime = systime_to_unix!(metadata.modified());
 
     if metadata.file_type().is_symlink() {
-        let target = fs::read_link(&path)?;
+        let target = fs::read_link(path)?;
         stat = Stat::Symlink(target);
     } else if metadata.file_type().is_dir() {
         stat = Stat::Directory;
@@ -152,10 +204,6 @@ fn stat(path: PathBuf, settings: &Settings) -> io::Result<File>
         }
     }
 
-    let file = File::new(path, stat, mtime);
-    if settings.md5() {
-        file.calc_md5()
-    } else {
-        Ok(file)
-    }
+    Ok(File::new(settings.db_path(path), stat, mtime))
 }
+
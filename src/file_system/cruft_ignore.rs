@@ -0,0 +1,132 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::path::{Path,PathBuf};
+use std::sync::Arc;
+
+use ignore::Match;
+use ignore::gitignore::{Gitignore,GitignoreBuilder};
+
+use crate::Settings;
+use super::walk::Entry;
+
+pub const CRUFT_IGNORE: &str = ".cruftignore";
+
+// The `.cruftignore` files that apply to a directory, from the closest
+// directory outwards. Each directory shares the rules of its parent.
+#[derive(Clone,Default)]
+pub struct CruftIgnore {
+    head: Option<Arc<Node>>
+}
+
+struct Node {
+    parent: Option<Arc<Node>>,
+    dir: PathBuf,
+    gitignore: Gitignore
+}
+
+impl CruftIgnore {
+    // Load the `.cruftignore` files from the parent directories of a scan
+    // path, up to and including `eroot`.
+    pub fn load_parents(path: &Path, eroot: &Path, settings: &Settings) -> Self {
+        let mut dirs: Vec<&Path> = path.ancestors().skip(1)
+            .take_while(|dir| dir.starts_with(eroot))
+            .collect();
+        dirs.reverse();
+
+        dirs.into_iter().fold(CruftIgnore::default(), |ignore, dir| {
+            ignore.enter(dir, settings)
+        })
+    }
+
+    // Returns `false` for `.cruftignore` files themselves and for entries
+    // ignored by a `.cruftignore` file in one of their parent directories.
+    pub fn accept(&self, entry: &Entry) -> bool {
+        let is_dir = entry.is_dir();
+        if !is_dir && entry.file_name() == CRUFT_IGNORE {
+            return false;
+        }
+
+        !self.is_ignored(entry.path(), is_dir)
+    }
+
+    // The rules for the contents of a directory, including its own
+    // `.cruftignore` file if it has one.
+    pub fn enter(&self, dir: &Path, settings: &Settings) -> Self {
+        match load(dir, settings) {
+            Some(gitignore) => {
+                let node = Node {
+                    parent: self.head.clone(),
+                    dir: dir.to_path_buf(),
+                    gitignore
+                };
+                CruftIgnore { head: Some(Arc::new(node)) }
+            },
+
+            None => self.clone()
+        }
+    }
+
+    // The `.cruftignore` closest to the path takes precedence, as with
+    // `.gitignore` files.
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut node = self.head.as_ref();
+        while let Some(current) = node {
+            if path.starts_with(&current.dir) {
+                match current.gitignore.matched(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => { }
+                }
+            }
+
+            node = current.parent.as_ref();
+        }
+
+        false
+    }
+}
+
+fn load(dir: &Path, settings: &Settings) -> Option<Gitignore> {
+    let path = dir.join(CRUFT_IGNORE);
+    if !path.is_file() {
+        return None;
+    }
+
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(err) = builder.add(&path) {
+        if settings.verbose() {
+            eprintln!("Error reading {}: {}", path.display(), err);
+        }
+    }
+
+    match builder.build() {
+        Ok(gitignore) => Some(gitignore),
+        Err(err) => {
+            if settings.verbose() {
+                eprintln!("Error reading {}: {}", path.display(), err);
+            }
+            None
+        }
+    }
+}
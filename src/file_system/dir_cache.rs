@@ -0,0 +1,169 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::Metadata;
+use std::os::unix::ffi::{OsStrExt,OsStringExt};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path,PathBuf};
+use std::sync::atomic::{AtomicBool,Ordering};
+use std::sync::{Mutex,OnceLock};
+use std::{fs,io};
+
+use serde::{Deserialize,Serialize};
+
+use crate::Settings;
+use crate::cache;
+
+const VERSION: u32 = 2;
+
+static INSTANCE: OnceLock<DirCache> = OnceLock::new();
+
+// Directory listings saved between runs, keyed by the device and inode of
+// the directory rather than its path. A listing is reused while the
+// modification time and change time of the directory are unchanged, as
+// adding, removing or renaming an entry updates them. The cache file is
+// only written when a listing has been added or removed.
+pub struct DirCache {
+    path: Option<PathBuf>,
+    dirs: Mutex<HashMap<(u64, u64), Listing>>,
+    changed: AtomicBool
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+struct Stamp {
+    mtime_ns: i64,
+    ctime_ns: i64
+}
+
+#[derive(Serialize,Deserialize)]
+struct Listing {
+    stamp: Stamp,
+    children: Vec<(Vec<u8>, bool)>,
+    #[serde(skip)]
+    used: bool
+}
+
+impl DirCache {
+    pub fn get() -> &'static DirCache {
+        INSTANCE.get_or_init(|| {
+            let settings = Settings::get();
+            if !settings.dir_cache() {
+                return DirCache {
+                    path: None,
+                    dirs: Mutex::default(),
+                    changed: AtomicBool::new(false)
+                };
+            }
+
+            let path = cache_path(&settings);
+            let dirs = cache::load(&path, VERSION);
+
+            DirCache { path: Some(path), dirs: Mutex::new(dirs), changed: AtomicBool::new(false) }
+        })
+    }
+
+    // The names of the entries in a directory, and whether each is a
+    // directory. The cached listing is used if the directory has not
+    // changed, otherwise the directory is read and the listing cached.
+    pub fn list(&self, dir: &Path, metadata: &Metadata) -> io::Result<Vec<(OsString, bool)>> {
+        if self.path.is_none() {
+            return read_dir(dir);
+        }
+
+        let key = (metadata.dev(), metadata.ino());
+        let stamp = Stamp::new(metadata);
+        if let Some(listing) = self.dirs.lock().unwrap().get_mut(&key) {
+            if listing.stamp == stamp {
+                listing.used = true;
+                return Ok(listing.children.iter()
+                    .map(|(name, is_dir)| (OsString::from_vec(name.clone()), *is_dir))
+                    .collect());
+            }
+        }
+
+        let children = read_dir(dir)?;
+        if !cache::is_racy(stamp.mtime_ns, stamp.ctime_ns) {
+            let listing = Listing {
+                stamp,
+                children: children.iter()
+                    .map(|(name, is_dir)| (name.as_bytes().to_vec(), *is_dir))
+                    .collect(),
+                used: true
+            };
+            self.dirs.lock().unwrap().insert(key, listing);
+            self.changed.store(true, Ordering::Relaxed);
+        }
+
+        Ok(children)
+    }
+
+    // Write the cache to disk. When `prune` is set, only the listings of
+    // directories visited during this run are kept.
+    pub fn save(&self, prune: bool) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return
+        };
+
+        let mut dirs = self.dirs.lock().unwrap();
+        if prune {
+            let len = dirs.len();
+            dirs.retain(|_, listing| listing.used);
+            if dirs.len() != len {
+                self.changed.store(true, Ordering::Relaxed);
+            }
+        }
+
+        if self.changed.load(Ordering::Relaxed) {
+            cache::store(path, VERSION, &*dirs);
+        }
+    }
+}
+
+impl Stamp {
+    fn new(metadata: &Metadata) -> Stamp {
+        Stamp {
+            mtime_ns: cache::ns(metadata.mtime(), metadata.mtime_nsec()),
+            ctime_ns: cache::ns(metadata.ctime(), metadata.ctime_nsec())
+        }
+    }
+}
+
+fn read_dir(dir: &Path) -> io::Result<Vec<(OsString, bool)>> {
+    fs::read_dir(dir)?.map(|entry| {
+        let entry = entry?;
+        let is_dir = entry.file_type()?.is_dir();
+        Ok((entry.file_name(), is_dir))
+    }).collect()
+}
+
+// Each root directory has its own cache file, named after the MD5 sum of
+// the root path.
+fn cache_path(settings: &Settings) -> PathBuf {
+    let eroot = settings.eroot();
+    let eroot = fs::canonicalize(&eroot).unwrap_or(eroot);
+    let digest = md5::compute(eroot.as_os_str().as_bytes());
+    settings.cache_dir().join(format!("dirs-{:x}.cache", digest))
+}
@@ -23,19 +23,22 @@
 
 use std::cell::RefCell;
 use std::io::Read;
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::{Path,PathBuf};
 use std::{fmt,fs,io};
 use md5;
 
 use crate::catalog::file::FileType;
-use crate::file_info::FileInfo;
+use crate::escape::escape;
+use super::md5_cache::Md5Cache;
 
 #[derive(Clone,Debug)]
 pub struct File {
     path: PathBuf,
     stat: RefCell<Stat>,
     mtime: u64,
-    md5: Option<String>
+    md5: Option<md5::Digest>,
+    note: Option<String>
 }
 
 #[derive(Clone,Debug,Hash)]
@@ -49,8 +52,13 @@ pub enum Stat {
     CharDevice,
     Fifo,
     Socket,
-    MD5,
-    MTIME
+    Md5,
+    Mtime,
+    Missing,
+    TypeMismatch,
+    Relinked,
+    OwnedDangling,
+    Dangling
 }
 
 impl File {
@@ -60,14 +68,54 @@ impl File {
         File {
             path: path.as_ref().to_path_buf(),
             stat: RefCell::new(stat),
-            mtime: mtime,
-            md5: None
+            mtime,
+            md5: None,
+            note: None
         }
     }
 
-    pub fn calc_md5(mut self) -> io::Result<Self> {
+    // Attach a note, such as the owning package, to be displayed after
+    // the path.
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+
+    pub fn stat(&self) -> Stat {
+        self.stat.borrow().clone()
+    }
+
+    pub fn set_stat(&self, stat: Stat) {
+        self.stat.replace(stat);
+    }
+
+    // Calculate the MD5 sum of a regular file, or reuse the cached sum if
+    // the file has not changed. The file is opened without blocking or
+    // following symlinks, and anything other than a regular file is
+    // rejected, so FIFOs and devices are never read.
+    pub fn calc_md5<P>(mut self, path: P) -> io::Result<Self>
+        where P: AsRef<Path>
+    {
+        if !self.stat().is_type(&FileType::Obj) {
+            return Err(not_regular_file());
+        }
+
+        let cache = Md5Cache::get();
+        if let Some(md5) = cache.lookup(&fs::symlink_metadata(&path)?) {
+            self.md5 = Some(md5);
+            return Ok(self);
+        }
+
         let mut context = md5::Context::new();
-        let mut file = fs::File::open(&self.path)?;
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_NOFOLLOW)
+            .open(path)?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(not_regular_file());
+        }
+
         let mut buf: [u8; 8192] = [0; 8192];
 
         loop {
@@ -77,66 +125,77 @@ impl File {
         }
 
         let digest = context.finalize();
-        self.md5 = Some(format!("{:x}", digest));
+        self.md5 = Some(digest);
+        cache.insert(&metadata, digest);
 
         Ok(self)
     }
 
-    pub fn to_file_info(self) -> Box<dyn FileInfo> {
-        Box::new(self)
-    }
-}
-
-impl FileInfo for File {
-    fn path(&self) -> &Path {
+    pub fn path(&self) -> &Path {
         &self.path
     }
 
-    fn file_type(&self) -> FileType {
-        let stat = self.stat.borrow();
-        match &*stat {
-            Stat::Directory => FileType::Dir,
-            Stat::Regular => FileType::Obj,
-            Stat::Executable => FileType::Obj,
-            Stat::Suid => FileType::Obj,
-            Stat::Symlink(link) => FileType::Sym(link.clone()),
-            Stat::BlockDevice => FileType::Obj,
-            Stat::CharDevice => FileType::Obj,
-            Stat::Fifo => FileType::Obj,
-            Stat::Socket => FileType::Obj,
-            Stat::MD5 => FileType::Obj,
-            Stat::MTIME => FileType::Obj
+    // Whether the MD5 sum matches the sum recorded in the package database.
+    // A file that does not match is marked as modified.
+    pub fn md5_matches(&self, md5: Option<md5::Digest>) -> bool {
+        if self.md5 != md5 {
+            self.stat.replace(Stat::Md5);
+            return false;
         }
-    }
-
-    fn mtime(&self) -> u64 {
-        self.mtime
-    }
 
-    fn md5(&self) -> Option<&str> {
-        self.md5.as_deref()
+        true
     }
 
-    fn md5_matches(&self, other: &dyn FileInfo) -> bool {
-        if other.file_type() == FileType::Obj &&
-            self.md5() != other.md5()
-        {
-            self.stat.replace(Stat::MD5);
+    // Whether the modification time matches the time recorded in the
+    // package database. A file that does not match is marked as modified.
+    pub fn mtime_matches(&self, mtime: u64) -> bool {
+        if self.mtime != mtime {
+            self.stat.replace(Stat::Mtime);
             return false;
         }
 
-        return true;
+        true
     }
+}
 
-    fn mtime_matches(&self, other: &dyn FileInfo) -> bool {
-        if other.file_type() == FileType::Obj &&
-            self.mtime() != other.mtime()
-        {
-            self.stat.replace(Stat::MTIME);
-            return false;
+impl Stat {
+    // The package database entry type corresponding to the file type.
+    // Special files are treated as `obj` entries.
+    pub fn file_type(&self) -> FileType {
+        match self {
+            Stat::Directory => FileType::Dir,
+            Stat::Symlink(link) => FileType::Sym(link.clone()),
+            Stat::Fifo => FileType::Fif,
+            Stat::BlockDevice | Stat::CharDevice => FileType::Dev,
+            _ => FileType::Obj
         }
+    }
+
+    // Whether the file is of the type recorded in the package database.
+    pub fn is_type(&self, file_type: &FileType) -> bool {
+        matches!((self, file_type),
+            (Stat::Directory, FileType::Dir) |
+            (Stat::Regular, FileType::Obj) |
+            (Stat::Executable, FileType::Obj) |
+            (Stat::Suid, FileType::Obj) |
+            (Stat::Symlink(_), FileType::Sym(_)) |
+            (Stat::Fifo, FileType::Fif) |
+            (Stat::BlockDevice, FileType::Dev) |
+            (Stat::CharDevice, FileType::Dev))
+    }
 
-        return true;
+    // A description of the file type, using the names of the types
+    // recorded in CONTENTS files where possible.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Stat::Directory => "dir",
+            Stat::Symlink(_) => "sym",
+            Stat::BlockDevice => "block device",
+            Stat::CharDevice => "character device",
+            Stat::Fifo => "fifo",
+            Stat::Socket => "socket",
+            _ => "obj"
+        }
     }
 }
 
@@ -152,8 +211,13 @@ impl fmt::Display for Stat {
             Stat::CharDevice => 'C',
             Stat::Fifo => 'F',
             Stat::Socket => 'Z',
-            Stat::MD5 => 'M',
-            Stat::MTIME => 'T'
+            Stat::Md5 => 'M',
+            Stat::Mtime => 'T',
+            Stat::Missing => 'X',
+            Stat::TypeMismatch => 'K',
+            Stat::Relinked => 'P',
+            Stat::OwnedDangling => 'O',
+            Stat::Dangling => 'U'
         };
 
         write!(f, "{}", ch)
@@ -162,6 +226,15 @@ impl fmt::Display for Stat {
 
 impl fmt::Display for File {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} {}", self.stat.borrow(), self.path.to_string_lossy())
+        write!(f, "{} {}", self.stat.borrow(), escape(&self.path))?;
+        if let Some(note) = &self.note {
+            write!(f, " ({})", note)?;
+        }
+
+        Ok(())
     }
 }
+
+fn not_regular_file() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "not a regular file")
+}
@@ -0,0 +1,142 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::Metadata;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::{Mutex,OnceLock};
+
+use serde::{Deserialize,Serialize};
+
+use crate::Settings;
+use crate::cache;
+
+const VERSION: u32 = 1;
+
+static INSTANCE: OnceLock<Md5Cache> = OnceLock::new();
+
+// MD5 sums saved between runs, in a file for each root directory. A sum
+// is reused only while the device, inode, size, modification time and
+// change time of the file are the same as when it was calculated.
+pub struct Md5Cache {
+    path: Option<PathBuf>,
+    entries: Mutex<HashMap<Key, Entry>>
+}
+
+#[derive(Clone,Copy,Debug,Hash,PartialEq,Eq,Serialize,Deserialize)]
+struct Key {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime_ns: i64,
+    ctime_ns: i64
+}
+
+struct Entry {
+    digest: [u8; 16],
+    used: bool
+}
+
+impl Md5Cache {
+    pub fn get() -> &'static Md5Cache {
+        INSTANCE.get_or_init(|| {
+            let settings = Settings::get();
+            if !settings.md5_cache() {
+                return Md5Cache { path: None, entries: Mutex::default() };
+            }
+
+            let path = cache_path(&settings);
+            let entries: Vec<(Key, [u8; 16])> = cache::load(&path, VERSION);
+            let entries = entries.into_iter()
+                .map(|(key, digest)| (key, Entry { digest, used: false }))
+                .collect();
+
+            Md5Cache { path: Some(path), entries: Mutex::new(entries) }
+        })
+    }
+
+    // The cached MD5 sum of a regular file, if it has not changed since
+    // the sum was calculated.
+    pub fn lookup(&self, metadata: &Metadata) -> Option<md5::Digest> {
+        if self.path.is_none() || !metadata.is_file() {
+            return None;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(&Key::new(metadata))?;
+        entry.used = true;
+        Some(md5::Digest(entry.digest))
+    }
+
+    // Cache the MD5 sum of a file. Sums of files changed too recently are
+    // not cached, as a rewrite of the same size within the same timestamp
+    // tick would leave the stale sum in place.
+    pub fn insert(&self, metadata: &Metadata, digest: md5::Digest) {
+        let key = Key::new(metadata);
+        if self.path.is_none() || cache::is_racy(key.mtime_ns, key.ctime_ns) {
+            return;
+        }
+
+        let entry = Entry { digest: digest.0, used: true };
+        self.entries.lock().unwrap().insert(key, entry);
+    }
+
+    // Write the cache to disk. When `prune` is set, only the sums that
+    // were looked up or calculated during this run are kept.
+    pub fn save(&self, prune: bool) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return
+        };
+
+        let entries = self.entries.lock().unwrap();
+        let entries: Vec<(Key, [u8; 16])> = entries.iter()
+            .filter(|(_, entry)| entry.used || !prune)
+            .map(|(key, entry)| (*key, entry.digest))
+            .collect();
+
+        cache::store(path, VERSION, &entries);
+    }
+}
+
+impl Key {
+    fn new(metadata: &Metadata) -> Key {
+        Key {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            size: metadata.size(),
+            mtime_ns: cache::ns(metadata.mtime(), metadata.mtime_nsec()),
+            ctime_ns: cache::ns(metadata.ctime(), metadata.ctime_nsec())
+        }
+    }
+}
+
+fn cache_path(settings: &Settings) -> PathBuf {
+    let eroot = settings.eroot();
+    let eroot = fs::canonicalize(&eroot).unwrap_or(eroot);
+    let digest = md5::compute(eroot.as_os_str().as_bytes());
+    settings.cache_dir().join(format!("md5-{:x}.cache", digest))
+}
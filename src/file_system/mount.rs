@@ -0,0 +1,318 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::collections::{HashMap,HashSet};
+use std::ffi::{CString,OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path,PathBuf};
+use std::sync::{Mutex,OnceLock};
+use std::{fs,io,mem};
+
+use crate::Settings;
+use super::walk::Entry;
+
+// The mount table, read once from `/proc/self/mountinfo`.
+static MOUNT_TABLE: OnceLock<Option<Vec<Mount>>> = OnceLock::new();
+
+// File system magic numbers as reported by statfs(2), used to identify a
+// mount point missing from the mount table.
+const FS_TYPES: &[(&str, u32)] = &[
+    ("9p",          0x0102_1997),
+    ("autofs",      0x0000_0187),
+    ("binfmt_misc", 0x4249_4e4d),
+    ("bpf",         0xcafe_4a11),
+    ("btrfs",       0x9123_683e),
+    ("ceph",        0x00c3_6400),
+    ("cgroup",      0x0027_e0eb),
+    ("cgroup2",     0x6367_7270),
+    ("cifs",        0xff53_4d42),
+    ("configfs",    0x6265_6570),
+    ("debugfs",     0x6462_6720),
+    ("devpts",      0x0000_1cd1),
+    ("efivarfs",    0xde5e_81e4),
+    ("ext4",        0x0000_ef53),
+    ("f2fs",        0xf2f5_2010),
+    ("fuse",        0x6573_5546),
+    ("hugetlbfs",   0x9584_58f6),
+    ("iso9660",     0x0000_9660),
+    ("mqueue",      0x1980_0202),
+    ("nfs",         0x0000_6969),
+    ("nsfs",        0x6e73_6673),
+    ("overlay",     0x794c_7630),
+    ("proc",        0x0000_9fa0),
+    ("pstore",      0x6165_676c),
+    ("ramfs",       0x8584_58f6),
+    ("securityfs",  0x7363_6673),
+    ("smb2",        0xfe53_4d42),
+    ("squashfs",    0x7371_7368),
+    ("sysfs",       0x6265_6572),
+    ("tmpfs",       0x0102_1994),
+    ("tracefs",     0x7472_6163),
+    ("vfat",        0x0000_4d44),
+    ("xfs",         0x5846_5342),
+    ("zfs",         0x2fc1_2fc1)
+];
+
+// A mount point and the type of the file system mounted on it, such as
+// `fuse.sshfs`.
+struct Mount {
+    path: PathBuf,
+    fs_type: String
+}
+
+// Tracks the mount points encountered while traversing a single scan path.
+pub struct Mounts<'a> {
+    dev: u64,
+    skip: Mutex<HashMap<u64, bool>>,
+    visited: &'a Visited
+}
+
+// The directories visited across all scan paths, keyed by device and inode,
+// so that a directory exposed more than once by bind mounts is traversed
+// only once. The smallest path to a directory is kept regardless of the
+// order in which directories are traversed, so the result does not depend
+// on thread scheduling. A directory can only be reached through more than
+// one path if it is mounted somewhere, so only the directories that are
+// the same as a mount point or a scan path are recorded, unless the mount
+// points cannot be read.
+pub struct Visited {
+    tracked: Option<HashSet<(u64, u64)>>,
+    dirs: Mutex<HashMap<(u64, u64), PathBuf>>,
+    replaced: Mutex<Vec<PathBuf>>
+}
+
+impl<'a> Mounts<'a> {
+    pub fn new<P>(path: P, visited: &'a Visited) -> Self
+        where P: AsRef<Path>
+    {
+        let dev = match fs::metadata(path) {
+            Ok(metadata) => metadata.dev(),
+            Err(_) => 0
+        };
+
+        Mounts { dev, skip: Mutex::new(HashMap::new()), visited }
+    }
+
+    // Returns `false` for directories that have already been visited through
+    // another path, such as a bind mount, and for mount points whose file
+    // system type should be skipped. When `one_file_system` is set, all
+    // mount points are skipped.
+    pub fn accept(&self, entry: &Entry, settings: &Settings) -> bool {
+        let metadata = match entry.metadata() {
+            Some(metadata) => metadata,
+            None => return true
+        };
+
+        let dev = metadata.dev();
+        let key = (dev, metadata.ino());
+        if self.visited.is_tracked(key) && !self.visited.visit(key, entry.path(), settings) {
+            return false;
+        }
+
+        if dev == self.dev {
+            return true;
+        }
+
+        if settings.one_file_system() {
+            return false;
+        }
+
+        let mut skip = self.skip.lock().unwrap();
+        !*skip.entry(dev).or_insert_with(|| {
+            is_skipped_fs(entry.path(), settings)
+        })
+    }
+
+    // Whether a path lies beneath a directory that was replaced by a
+    // smaller path to the same directory.
+    pub fn is_replaced(&self, path: &Path) -> bool {
+        self.visited.is_replaced(path)
+    }
+}
+
+impl Visited {
+    pub fn new(scan_paths: &[PathBuf]) -> Visited {
+        let tracked = mount_table().map(|mounts| {
+            mounts.iter()
+                .map(|mount| &mount.path)
+                .chain(scan_paths)
+                .filter_map(|path| fs::metadata(path).ok())
+                .map(|metadata| (metadata.dev(), metadata.ino()))
+                .collect()
+        });
+
+        Visited { tracked, dirs: Mutex::default(), replaced: Mutex::default() }
+    }
+
+    fn is_tracked(&self, key: (u64, u64)) -> bool {
+        match &self.tracked {
+            Some(tracked) => tracked.contains(&key),
+            None => true
+        }
+    }
+
+    // Record a visit to a directory. Returns `false` if the directory has
+    // already been visited through a smaller path. If it was visited
+    // through a larger path, that path is replaced and the entries found
+    // beneath it are to be discarded.
+    fn visit(&self, key: (u64, u64), path: &Path, settings: &Settings) -> bool {
+        let mut dirs = self.dirs.lock().unwrap();
+        let visited = match dirs.get_mut(&key) {
+            Some(visited) => visited,
+            None => {
+                dirs.insert(key, path.to_path_buf());
+                return true;
+            }
+        };
+
+        let accept = path < visited.as_path();
+        let skipped = if accept {
+            let replaced = std::mem::replace(visited, path.to_path_buf());
+            if self.is_replaced(&replaced) {
+                return true;
+            }
+
+            self.replaced.lock().unwrap().push(replaced.clone());
+            replaced
+        } else {
+            path.to_path_buf()
+        };
+
+        if settings.verbose() {
+            eprintln!("Skipping already visited directory: {}", skipped.display());
+        }
+
+        accept
+    }
+
+    // Whether a path lies beneath a directory that was replaced by a
+    // smaller path to the same directory.
+    fn is_replaced(&self, path: &Path) -> bool {
+        let replaced = self.replaced.lock().unwrap();
+        replaced.iter().any(|dir| path.starts_with(dir))
+    }
+}
+
+// The mounts of the current mount namespace, in the order they were
+// mounted, or `None` if the mount table cannot be read.
+fn mount_table() -> Option<&'static [Mount]> {
+    MOUNT_TABLE.get_or_init(|| read_mount_table().ok()).as_deref()
+}
+
+// Each line holds the mount point in its fifth field and the file system
+// type in the field following the ` - ` separator.
+fn read_mount_table() -> io::Result<Vec<Mount>> {
+    let mountinfo = fs::read("/proc/self/mountinfo")?;
+    Ok(mountinfo.split(|byte| *byte == b'\n').filter_map(|line| {
+        let mut fields = line.split(|byte| *byte == b' ');
+        let path = fields.nth(4)?;
+        let fs_type = fields.skip_while(|field| *field != b"-").nth(1)?;
+        Some(Mount {
+            path: PathBuf::from(OsStr::from_bytes(&unescape(path))),
+            fs_type: String::from_utf8_lossy(&unescape(fs_type)).into_owned()
+        })
+    }).collect())
+}
+
+// Decode the octal escapes, such as `\040` for a space, used for mount
+// points in `/proc/self/mountinfo`.
+fn unescape(field: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut i = 0;
+    while i < field.len() {
+        let code = field.get(i + 1..i + 4)
+            .filter(|_| field[i] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match code {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 4;
+            },
+
+            None => {
+                bytes.push(field[i]);
+                i += 1;
+            }
+        }
+    }
+
+    bytes
+}
+
+fn is_skipped_fs(path: &Path, settings: &Settings) -> bool {
+    let skip_fs_types = match settings.skip_fs_types() {
+        Some(skip_fs_types) => skip_fs_types,
+        None => return false
+    };
+
+    let name = match fs_type(path) {
+        Ok(Some(name)) => name,
+        _ => return false
+    };
+
+    let skip = skip_fs_types.iter().any(|pattern| type_matches(pattern, name));
+    if skip && settings.verbose() {
+        eprintln!("Skipping {} file system: {}", name, path.display());
+    }
+
+    skip
+}
+
+// A pattern such as `fuse.*` matches every subtype of a file system type,
+// as well as the type itself, which is all that statfs(2) reports for FUSE
+// file systems.
+fn type_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix(".*") {
+        Some(base) => {
+            name == base || name.strip_prefix(base).is_some_and(|rest| rest.starts_with('.'))
+        },
+
+        None => pattern == name
+    }
+}
+
+// The type of the file system mounted at a path. The mount table is
+// consulted first, as the statfs(2) magic number cannot tell FUSE file
+// systems apart. Of several mounts on the same path, the last is visible.
+fn fs_type(path: &Path) -> io::Result<Option<&'static str>> {
+    let mount = mount_table().and_then(|mounts| {
+        mounts.iter().rfind(|mount| mount.path == path)
+    });
+
+    if let Some(mount) = mount {
+        return Ok(Some(&mount.fs_type));
+    }
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut buf: libc::statfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut buf) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let magic = buf.f_type as u32;
+    Ok(FS_TYPES.iter()
+        .find(|(_, m)| *m == magic)
+        .map(|(name, _)| *name))
+}
@@ -0,0 +1,99 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::path::{Component,Path,PathBuf};
+use std::{fs,io};
+
+use crate::Settings;
+
+// Maximum number of symlinks followed while resolving a path, as with
+// MAXSYMLINKS on Linux.
+const MAX_SYMLINKS: usize = 40;
+
+pub enum Broken {
+    // The path does not exist.
+    Missing(PathBuf),
+    // A non-directory was found where a directory was expected.
+    NotADirectory(PathBuf),
+    // Too many symlinks were encountered.
+    Loop,
+    Io(io::Error)
+}
+
+// Resolve a path recorded in the package database, following symlinks in
+// every component. Absolute symlink targets are resolved relative to the
+// root directory rather than to `/`.
+pub fn resolve(path: &Path, settings: &Settings) -> Result<PathBuf,Broken> {
+    let mut resolved = PathBuf::from("/");
+    let mut pending: VecDeque<OsString> = components(path).collect();
+    let mut links = 0;
+
+    while let Some(name) = pending.pop_front() {
+        if name == ".." {
+            resolved.pop();
+            continue;
+        }
+
+        let candidate = resolved.join(&name);
+        let metadata = match fs::symlink_metadata(settings.root_path(&candidate)) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(Broken::Missing(candidate));
+            },
+            Err(err) => return Err(Broken::Io(err))
+        };
+
+        if metadata.file_type().is_symlink() {
+            links += 1;
+            if links > MAX_SYMLINKS {
+                return Err(Broken::Loop);
+            }
+
+            let target = fs::read_link(settings.root_path(&candidate))
+                .map_err(Broken::Io)?;
+            for name in components(&target).rev() {
+                pending.push_front(name);
+            }
+
+            if target.is_absolute() {
+                resolved = PathBuf::from("/");
+            }
+        } else if !metadata.is_dir() && !pending.is_empty() {
+            return Err(Broken::NotADirectory(candidate));
+        } else {
+            resolved = candidate;
+        }
+    }
+
+    Ok(resolved)
+}
+
+fn components(path: &Path) -> impl DoubleEndedIterator<Item = OsString> + '_ {
+    path.components().filter_map(|component| match component {
+        Component::Normal(name) => Some(name.to_os_string()),
+        Component::ParentDir => Some(OsString::from("..")),
+        _ => None
+    })
+}
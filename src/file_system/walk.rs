@@ -0,0 +1,246 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::collections::{BTreeMap,BTreeSet};
+use std::ffi::{OsStr,OsString};
+use std::fs::Metadata;
+use std::path::{Path,PathBuf};
+use std::sync::Mutex;
+use std::{fs,io};
+
+use crate::Settings;
+use super::dir_cache::DirCache;
+
+// An entry encountered while traversing the file system. Symlinks are not
+// followed, so a symlink to a directory is not a directory.
+pub struct Entry {
+    path: PathBuf,
+    metadata: Option<Metadata>
+}
+
+// Decides which entries are visited. The state of a directory, such as
+// the `.cruftignore` rules that apply to it, is passed down to its
+// contents.
+pub trait Filter: Sync {
+    type State: Send + Sync;
+
+    // Returns `false` to skip an entry along with its contents.
+    fn accept(&self, entry: &Entry, state: &Self::State) -> bool;
+
+    // The state for the contents of an accepted directory.
+    fn enter(&self, dir: &Entry, state: &Self::State) -> Self::State;
+
+    // Returns `true` to discard the results for a path once every smaller
+    // path has been traversed.
+    fn discard(&self, path: &Path) -> bool;
+}
+
+impl Entry {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file_name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or(self.path.as_os_str())
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.metadata.is_some()
+    }
+
+    // The metadata of a directory, as read while traversing it.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    fn read(path: PathBuf, is_dir: Option<bool>) -> io::Result<Entry> {
+        if is_dir == Some(false) {
+            return Ok(Entry { path, metadata: None });
+        }
+
+        let metadata = fs::symlink_metadata(&path)
+            .map_err(|err| with_path(err, &path))?;
+        let metadata = if metadata.is_dir() { Some(metadata) } else { None };
+        Ok(Entry { path, metadata })
+    }
+}
+
+// Produces the results of a traversal. Results are paired with the path
+// beneath the root directory by which they are ordered, and are emitted in
+// that order as soon as every smaller path has been traversed.
+pub trait Visitor: Sync {
+    type Output: Send;
+
+    // The results for the top of the tree. `entry` is `None` if it could
+    // not be read. A top of the tree that is skipped is not visited.
+    fn visit(&self, path: &Path, entry: Option<&Entry>) -> Vec<(PathBuf, Self::Output)>;
+
+    // The results for the accepted entries of a directory, which are
+    // sorted by name. `skipped` holds the sorted names of the entries that
+    // exist but were not accepted.
+    fn visit_dir(&self, dir: &Entry, entries: &[Entry], skipped: &[OsString])
+        -> Vec<(PathBuf, Self::Output)>;
+
+    // Display a result. An error, such as the output being closed early,
+    // stops the traversal.
+    fn emit(&self, output: Self::Output) -> io::Result<()>;
+}
+
+// The results waiting for the directories that sort before them to be
+// traversed, and the error that stopped the traversal, if any.
+struct Merge<T> {
+    pending: BTreeSet<PathBuf>,
+    ready: BTreeMap<PathBuf, Vec<T>>,
+    error: Option<io::Error>
+}
+
+// Traverse a directory tree, passing the accepted entries of each
+// directory to the visitor. Directories are traversed in parallel on the
+// rayon thread pool, and results are emitted in order of path as each
+// subtree is completed. Directories are listed through the directory
+// cache, so unchanged directories are not read again. Returns the error
+// from the visitor that stopped the traversal early.
+pub fn walk<F, V>(path: PathBuf, state: &F::State, filter: &F, visitor: &V) -> io::Result<()>
+    where F: Filter,
+          V: Visitor
+{
+    let merge = Mutex::new(Merge {
+        pending: BTreeSet::new(),
+        ready: BTreeMap::new(),
+        error: None
+    });
+    let entry = match Entry::read(path.clone(), None) {
+        Ok(entry) if !filter.accept(&entry, state) => return Ok(()),
+        Ok(entry) => Some(entry),
+        Err(err) => {
+            report(err);
+            None
+        }
+    };
+
+    let results = visitor.visit(&path, entry.as_ref());
+    match entry {
+        Some(entry) if entry.is_dir() => {
+            let state = filter.enter(&entry, state);
+            merge.lock().unwrap().complete(None, results, vec![path], filter, visitor);
+            rayon::scope(|scope| walk_dir(scope, entry, state, filter, visitor, &merge));
+        },
+
+        _ => merge.lock().unwrap().complete(None, results, Vec::new(), filter, visitor)
+    }
+
+    match merge.into_inner().unwrap().error {
+        Some(err) => Err(err),
+        None => Ok(())
+    }
+}
+
+fn walk_dir<'a, F, V>(scope: &rayon::Scope<'a>, dir: Entry, state: F::State, filter: &'a F,
+                      visitor: &'a V, merge: &'a Mutex<Merge<V::Output>>)
+    where F: Filter,
+          V: Visitor
+{
+    if merge.lock().unwrap().error.is_some() {
+        return;
+    }
+
+    let metadata = dir.metadata().expect("Directory without metadata!");
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    match DirCache::get().list(dir.path(), metadata) {
+        Ok(mut listing) => {
+            listing.sort();
+            for (name, is_dir) in listing {
+                match Entry::read(dir.path().join(&name), Some(is_dir)) {
+                    Ok(entry) if filter.accept(&entry, &state) => entries.push(entry),
+                    Ok(_) => skipped.push(name),
+                    Err(err) => report(err)
+                }
+            }
+        },
+
+        Err(err) => report(with_path(err, dir.path()))
+    }
+
+    let results = visitor.visit_dir(&dir, &entries, &skipped);
+    let subdirs: Vec<Entry> = entries.into_iter().filter(Entry::is_dir).collect();
+    let paths = subdirs.iter().map(|subdir| subdir.path().to_path_buf()).collect();
+    merge.lock().unwrap().complete(Some(dir.path()), results, paths, filter, visitor);
+
+    for subdir in subdirs {
+        let state = filter.enter(&subdir, &state);
+        scope.spawn(move |scope| walk_dir(scope, subdir, state, filter, visitor, merge));
+    }
+}
+
+impl<T> Merge<T> {
+    // Record the results of a directory along with the subdirectories
+    // still to be traversed, then emit every result that no longer waits
+    // on a directory. Results are dropped once emitting has failed.
+    fn complete<F, V>(&mut self, dir: Option<&Path>, results: Vec<(PathBuf, T)>,
+                      subdirs: Vec<PathBuf>, filter: &F, visitor: &V)
+        where F: Filter,
+              V: Visitor<Output = T>
+    {
+        if self.error.is_some() {
+            return;
+        }
+
+        for (path, output) in results {
+            self.ready.entry(path).or_default().push(output);
+        }
+
+        self.pending.extend(subdirs);
+        if let Some(dir) = dir {
+            self.pending.remove(dir);
+        }
+
+        while let Some(entry) = self.ready.first_entry() {
+            if self.pending.first().is_some_and(|pending| entry.key() > pending) {
+                break;
+            }
+
+            let (path, outputs) = entry.remove_entry();
+            if filter.discard(&path) {
+                continue;
+            }
+
+            if let Err(err) = outputs.into_iter().try_for_each(|output| visitor.emit(output)) {
+                self.error = Some(err);
+                self.pending.clear();
+                self.ready.clear();
+                return;
+            }
+        }
+    }
+}
+
+fn report(err: io::Error) {
+    if Settings::get().verbose() {
+        eprintln!("Error accessing path: {}", err);
+    }
+}
+
+fn with_path(err: io::Error, path: &Path) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+}
@@ -25,75 +25,364 @@
 extern crate clap;
 extern crate serde;
 
-use std::any::Any;
-use std::borrow::Borrow;
-use std::collections::HashSet;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path,PathBuf};
+use std::process;
+use std::{fs,io};
 
+use rayon::prelude::*;
+
+mod cache;
 mod catalog;
-mod file_info;
+mod command;
+mod escape;
 mod file_system;
+mod path_tree;
 mod settings;
 mod symlink;
 
-use catalog::Catalog;
-use file_info::FileInfo;
-use file_system::{File,FileSystem};
+use catalog::{Catalog,Records};
+use command::Command;
+use escape::escape;
+use catalog::file::{FileType,Record};
+use file_system::{Entry,File,FileSystem,Visitor};
+use file_system::file::Stat;
+use file_system::md5_cache::Md5Cache;
+use file_system::resolve::{Broken,resolve};
+use path_tree::{PathId,PathTree};
 use settings::Settings;
 
 fn main() {
-    let pkg_files: HashSet<Box<dyn FileInfo>> = Catalog::read();
-    let fs_files: HashSet<Box<dyn FileInfo>> = FileSystem::read();
+    let settings = Settings::get();
+    match settings.command() {
+        Command::Scan => scan(),
+        Command::Check(atoms) => command::check::run(atoms),
+        Command::Collisions => command::collisions::run(),
+        Command::Owner(paths) => command::owner::run(paths)
+    }
+}
 
+fn scan() {
     let settings = Settings::get();
-    let mut diff: HashSet<_> = fs_files.difference(&pkg_files).collect();
-    if settings.md5() || settings.mtime() {
-        let modified = find_modified_files(&pkg_files, &fs_files);
-        diff.extend(modified);
+    let mut tree = PathTree::new();
+    let records = Catalog::read(&mut tree);
+    let result = FileSystem::walk(&Scan { tree: &tree, records: &records, settings: &settings });
+
+    // Sums of files that were not hashed are discarded only when the
+    // whole tree was scanned. The scan stops early, keeping the sums
+    // calculated so far, when the output is closed, such as by `head`.
+    if settings.md5() {
+        Md5Cache::get().save(result.is_ok() && settings.in_scope(Path::new("/")));
     }
 
-    let mut diff: Vec<File> = diff.iter().map(|file| {
-        let file: &dyn Any = file.as_ref();
-        match file.downcast_ref::<File>() {
-            Some(file) => file.clone(),
-            None => panic!("Unable to downcast File!")
+    Catalog::report_damaged();
+    if command::output_failed(result) {
+        process::exit(1);
+    }
+}
+
+// Compares the entries of each directory with the entries recorded in the
+// package database, displaying the differences as the file system is
+// traversed.
+struct Scan<'a> {
+    tree: &'a PathTree,
+    records: &'a Records,
+    settings: &'a Settings
+}
+
+// An entry of a directory to be compared, paired with the package database
+// path of the same name, or a package database path with no entry.
+enum Job<'a> {
+    Entry(&'a Entry, Option<PathId>),
+    Missing(PathId)
+}
+
+impl Visitor for Scan<'_> {
+    type Output = File;
+
+    fn visit(&self, path: &Path, entry: Option<&Entry>) -> Vec<(PathBuf, File)> {
+        let id = self.tree.find(&self.settings.db_path(path));
+        let mut results = Vec::new();
+        match entry {
+            Some(entry) => {
+                if let Some(file) = file_system::read_entry(entry, self.settings) {
+                    results = self.compare(path, file, id);
+                }
+            },
+
+            None => {
+                if let Some(id) = id {
+                    self.find_missing(id, false, &mut results);
+                }
+            }
         }
-    }).collect();
 
-    diff.sort_by(|a, b| a.path().cmp(b.path()));
-    for file in diff {
-        println!("{file}");
+        results
+    }
+
+    // The entries of the directory are merged with the package database
+    // paths beneath it, both being sorted by name, and are then compared
+    // in parallel. Package database paths at skipped entries are neither
+    // compared nor checked for being missing, so that ignored paths and
+    // skipped mount points are never accessed.
+    fn visit_dir(&self, dir: &Entry, entries: &[Entry], skipped: &[OsString])
+        -> Vec<(PathBuf, File)>
+    {
+        let children = self.tree.find(&self.settings.db_path(dir.path()))
+            .map_or(&[][..], |id| self.tree.children(id));
+        let mut children = children.iter().copied()
+            .filter(|id| skipped.binary_search_by(|name| {
+                name.as_os_str().cmp(self.tree.file_name(*id))
+            }).is_err())
+            .peekable();
+        let mut jobs = Vec::new();
+        for entry in entries {
+            let name = entry.file_name();
+            while let Some(id) = children.next_if(|id| self.tree.file_name(*id) < name) {
+                jobs.push(Job::Missing(id));
+            }
+
+            let id = children.next_if(|id| self.tree.file_name(*id) == name);
+            jobs.push(Job::Entry(entry, id));
+        }
+        jobs.extend(children.map(Job::Missing));
+
+        jobs.into_par_iter().flat_map_iter(|job| {
+            let mut results = Vec::new();
+            match job {
+                Job::Entry(entry, id) => self.compare_entry(entry, id, &mut results),
+                Job::Missing(id) => self.find_missing(id, false, &mut results)
+            }
+            results
+        }).collect()
+    }
+
+    fn emit(&self, file: File) -> io::Result<()> {
+        writeln!(io::stdout().lock(), "{file}")
     }
 }
 
-fn find_modified_files<'a>(pkg_files: &'a HashSet<Box<dyn FileInfo>>,
-                           fs_files:  &'a HashSet<Box<dyn FileInfo>>)
-    -> HashSet<&'a Box<dyn FileInfo>>
-{
-    let settings = Settings::get();
+impl Scan<'_> {
+    // Compare an entry of a directory. Package database paths beneath
+    // anything other than a directory are checked for being missing, as
+    // they are not traversed. Those beneath a recorded directory that has
+    // been replaced by a file other than a symlink are known to be gone.
+    fn compare_entry(&self, entry: &Entry, id: Option<PathId>,
+                     results: &mut Vec<(PathBuf, File)>)
+    {
+        match file_system::read_entry(entry, self.settings) {
+            Some(file) => {
+                let replaced = !matches!(file.stat(), Stat::Symlink(_));
+                results.append(&mut self.compare(entry.path(), file, id));
+                if let (Some(id), false) = (id, entry.is_dir()) {
+                    let gone = replaced && self.records.get(id).unwrap_or_default()
+                        .iter()
+                        .any(|record| *record.file_type() == FileType::Dir);
+                    for child in self.tree.children(id) {
+                        self.find_missing(*child, gone, results);
+                    }
+                }
+            },
 
-    let xset: HashSet<_> = pkg_files.iter()
-        .filter_map(|pkg_file| {
-            if fs_files.contains(pkg_file) {
-                return Some(pkg_file);
+            None => {
+                if let (Some(id), false) = (id, entry.is_dir()) {
+                    self.find_missing(id, false, results);
+                }
             }
+        }
+    }
 
+    // Compare a file with the entries recorded for the same path in the
+    // package database. A file not recorded with the same type is
+    // displayed unless it is displayed as mismatched, relinked or dangling.
+    fn compare(&self, root_path: &Path, file: File, id: Option<PathId>) -> Vec<(PathBuf, File)> {
+        let settings = self.settings;
+        let records = id.and_then(|id| self.records.get(id)).unwrap_or_default();
+        let mismatched = find_mismatched_file(&file, records);
+        let relinked = if settings.symlinks() {
+            find_relinked_file(&file, records)
+        } else {
+            None
+        };
+        let dangling = if settings.dangling() {
+            find_dangling_link(&file, records)
+        } else {
             None
+        };
+
+        let mut files = Vec::new();
+        let file_type = file.stat().file_type();
+        if !records.iter().any(|record| *record.file_type() == file_type) {
+            if mismatched.is_none() && relinked.is_none() && dangling.is_none() {
+                files.push(file);
+            }
+        } else if settings.md5() || settings.mtime() {
+            files.extend(find_modified_file(root_path, file, records));
+        }
+
+        files.extend(mismatched);
+        files.extend(dangling);
+        files.extend(relinked);
+
+        files.into_iter().map(|file| (root_path.to_path_buf(), file)).collect()
+    }
+
+    // Find the paths recorded in the package database at and beneath a
+    // path that does not exist. `gone` is set once a parent is known not
+    // to exist.
+    fn find_missing(&self, id: PathId, gone: bool, results: &mut Vec<(PathBuf, File)>) {
+        if !self.settings.missing() {
+            return;
+        }
+
+        let path = self.tree.path(id);
+        let root_path = self.settings.root_path(&path);
+        let gone = gone || matches!(fs::symlink_metadata(&root_path),
+                                    Err(err) if file_system::is_missing(&err));
+
+        if gone && !file_system::is_path_ignored(&path, self.settings) {
+            let mut file_types: Vec<&FileType> = Vec::new();
+            for record in self.records.get(id).unwrap_or_default() {
+                if file_types.contains(&record.file_type()) {
+                    continue;
+                }
+
+                file_types.push(record.file_type());
+                let file = File::new(&path, Stat::Missing, 0);
+                results.push((root_path.clone(), file.with_note(record.package())));
+            }
+        }
+
+        for child in self.tree.children(id) {
+            self.find_missing(*child, gone, results);
+        }
+    }
+}
+
+// Find a file whose type differs from the type of every entry recorded for
+// the same path in the package database.
+fn find_mismatched_file(file: &File, records: &[Record]) -> Option<File> {
+    let stat = file.stat();
+    if records.is_empty() || records.iter().any(|record| stat.is_type(record.file_type())) {
+        return None;
+    }
+
+    let note = format!("expected {}, found {}",
+                       records[0].file_type().name(), stat.type_name());
+    let file = file.clone().with_note(note);
+    file.set_stat(Stat::TypeMismatch);
+    Some(file)
+}
+
+// Find a symlink whose target differs from the target of every symlink
+// recorded for the same path in the package database.
+fn find_relinked_file(file: &File, records: &[Record]) -> Option<File> {
+    let target = match file.stat() {
+        Stat::Symlink(target) => target,
+        _ => return None
+    };
+
+    let targets: Vec<&PathBuf> = records.iter()
+        .filter_map(|record| match record.file_type() {
+            FileType::Sym(target) => Some(target),
+            _ => None
         }).collect();
 
-    fs_files.into_iter().filter_map(|fs_file| {
-        if let Some(xset_file) = xset.get(fs_file) {
-            let xset_file: &dyn FileInfo = (**xset_file).borrow();
-            if settings.md5() && !fs_file.md5_matches(xset_file)
-            {
-                return Some(fs_file);
+    if targets.is_empty() || targets.contains(&&target) {
+        return None;
+    }
+
+    let note = format!("expected {}, found {}",
+                       escape(targets[0]), escape(&target));
+    let file = file.clone().with_note(note);
+    file.set_stat(Stat::Relinked);
+    Some(file)
+}
+
+// Find a symlink whose target cannot be resolved. Symlinks owned by a
+// package are distinguished from unowned symlinks.
+fn find_dangling_link(file: &File, records: &[Record]) -> Option<File> {
+    let settings = Settings::get();
+    if !matches!(file.stat(), Stat::Symlink(_)) {
+        return None;
+    }
+
+    let note = match resolve(file.path(), &settings) {
+        Ok(_) => return None,
+        Err(Broken::Missing(path)) => {
+            format!("missing {}", escape(&path))
+        },
+
+        Err(Broken::NotADirectory(path)) => {
+            format!("not a directory {}", escape(&path))
+        },
+
+        Err(Broken::Loop) => String::from("symlink loop"),
+        Err(Broken::Io(err)) => {
+            if settings.verbose() {
+                eprintln!("Error resolving {}: {}", file.path().display(), err);
             }
+            return None;
+        }
+    };
+
+    let package = records.first().map(|record| record.package());
+    let file = match package {
+        Some(package) => {
+            file.clone().with_note(format!("{}: {}", package, note))
+        },
+
+        None => file.clone().with_note(note)
+    };
+
+    file.set_stat(if package.is_some() {
+        Stat::OwnedDangling
+    } else {
+        Stat::Dangling
+    });
+    Some(file)
+}
 
-            if settings.mtime() && !fs_file.mtime_matches(xset_file)
-            {
-                return Some(fs_file);
+// Find a file recorded in the package database whose MD5 sum or
+// modification time differs. Only regular files recorded as `obj` entries
+// are hashed. A path recorded by several packages is modified only if it
+// matches none of their entries, and is then displayed as having a
+// different modification time if its MD5 sum matches any of them.
+fn find_modified_file(root_path: &Path, file: File, records: &[Record]) -> Option<File> {
+    let settings = Settings::get();
+    if !file.stat().is_type(&FileType::Obj) {
+        return None;
+    }
+
+    let mut records: Vec<&Record> = records.iter()
+        .filter(|record| *record.file_type() == FileType::Obj)
+        .collect();
+    if records.is_empty() {
+        return None;
+    }
+
+    let mut file = file;
+    if settings.md5() {
+        file = match file.calc_md5(root_path) {
+            Ok(file) => file,
+            Err(err) => {
+                if settings.verbose() {
+                    eprintln!("Error reading {}: {}", root_path.display(), err);
+                }
+                return None;
             }
+        };
+
+        records.retain(|record| file.md5_matches(record.md5()));
+        if records.is_empty() {
+            return Some(file);
         }
+    }
+
+    if settings.mtime() && !records.iter().any(|record| file.mtime_matches(record.mtime())) {
+        return Some(file);
+    }
 
-        None
-    }).collect()
+    None
 }
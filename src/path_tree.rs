@@ -0,0 +1,366 @@
+//
+// Copyright (C) 2025 Robert Gill <rtgill82@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies of the Software, its documentation and marketing & publicity
+// materials, and acknowledgment shall be given in the documentation, materials
+// and software packages that this Software was used.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL
+// THE AUTHORS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+use std::collections::hash_map::RandomState;
+use std::ffi::OsStr;
+use std::hash::BuildHasher;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component,Path,PathBuf};
+
+// Identifies a path stored in a `PathTree`.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct PathId(u32);
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+struct NameId(u32);
+
+// Paths stored as a tree of components, each path being a parent and a
+// name. Names are interned into a single buffer, so each distinct name is
+// stored once and a path costs a few bytes however long it is.
+pub struct PathTree {
+    nodes: Vec<Node>,
+    children: Index,
+    sorted: Vec<PathId>,
+    sorted_ends: Vec<u32>,
+    names: Vec<u8>,
+    name_ends: Vec<u32>,
+    name_index: Index,
+    hasher: RandomState
+}
+
+#[derive(Clone,Copy,PartialEq)]
+struct Node {
+    parent: PathId,
+    name: NameId
+}
+
+// An open addressing hash table of identifiers, whose keys are looked up
+// by the caller. Empty slots are zero and other slots hold the identifier
+// plus one.
+struct Index {
+    slots: Vec<u32>,
+    len: usize
+}
+
+const ROOT: PathId = PathId(0);
+
+impl PathTree {
+    pub fn new() -> Self {
+        let mut tree = PathTree {
+            nodes: Vec::new(),
+            children: Index::new(),
+            sorted: Vec::new(),
+            sorted_ends: Vec::new(),
+            names: Vec::new(),
+            name_ends: Vec::new(),
+            name_index: Index::new(),
+            hasher: RandomState::new()
+        };
+
+        let name = tree.intern(OsStr::new(""));
+        tree.nodes.push(Node { parent: ROOT, name });
+        tree
+    }
+
+    // Add an absolute path, returning its identifier.
+    pub fn insert(&mut self, path: &Path) -> PathId {
+        path.components().fold(ROOT, |id, component| match component {
+            Component::Normal(name) => self.child(id, name),
+            Component::ParentDir => self.parent(id).unwrap_or(ROOT),
+            _ => id
+        })
+    }
+
+    // The identifier of a path, if it has been added.
+    pub fn find(&self, path: &Path) -> Option<PathId> {
+        path.components().try_fold(ROOT, |id, component| match component {
+            Component::Normal(name) => self.get(id, name),
+            Component::ParentDir => Some(self.parent(id).unwrap_or(ROOT)),
+            _ => Some(id)
+        })
+    }
+
+    fn get(&self, parent: PathId, name: &OsStr) -> Option<PathId> {
+        let bytes = name.as_bytes();
+        let hash = self.hasher.hash_one(bytes);
+        let name = self.name_index.find(hash, |id| self.name(NameId(id)) == bytes).ok()?;
+        let node = Node { parent, name: NameId(name) };
+        let hash = self.hasher.hash_one((parent.0, name));
+        self.children.find(hash, |id| self.nodes[id as usize] == node)
+            .ok()
+            .map(PathId)
+    }
+
+    // Add an entry to a directory, returning its identifier.
+    pub fn child(&mut self, parent: PathId, name: &OsStr) -> PathId {
+        let node = Node { parent, name: self.intern(name) };
+        let nodes = &self.nodes;
+        let hasher = &self.hasher;
+        let hash = |node: &Node| hasher.hash_one((node.parent.0, node.name.0));
+        self.children.reserve(|id| hash(&nodes[id as usize]));
+
+        match self.children.find(hash(&node), |id| nodes[id as usize] == node) {
+            Ok(id) => PathId(id),
+            Err(slot) => {
+                let id = self.nodes.len() as u32;
+                self.nodes.push(node);
+                self.children.insert(slot, id);
+                PathId(id)
+            }
+        }
+    }
+
+    pub fn parent(&self, id: PathId) -> Option<PathId> {
+        if id == ROOT {
+            None
+        } else {
+            Some(self.nodes[id.0 as usize].parent)
+        }
+    }
+
+    pub fn file_name(&self, id: PathId) -> &OsStr {
+        OsStr::from_bytes(self.name(self.nodes[id.0 as usize].name))
+    }
+
+    // The entries of a directory in order of name, as of the last call to
+    // `sort`.
+    pub fn children(&self, id: PathId) -> &[PathId] {
+        let index = id.0 as usize;
+        let start = match index {
+            0 => 0,
+            _ => self.sorted_ends.get(index - 1).map_or(0, |end| *end as usize)
+        };
+
+        let end = self.sorted_ends.get(index).map_or(start, |end| *end as usize);
+        &self.sorted[start..end]
+    }
+
+    // Sort the entries of every directory by name.
+    pub fn sort(&mut self) {
+        let mut sorted: Vec<PathId> = (1..self.nodes.len() as u32).map(PathId).collect();
+        sorted.sort_unstable_by(|a, b| {
+            let a_node = self.nodes[a.0 as usize];
+            let b_node = self.nodes[b.0 as usize];
+            a_node.parent.cmp(&b_node.parent)
+                .then_with(|| self.name(a_node.name).cmp(self.name(b_node.name)))
+        });
+
+        let mut sorted_ends = vec![0; self.nodes.len()];
+        for id in &sorted {
+            sorted_ends[self.nodes[id.0 as usize].parent.0 as usize] += 1;
+        }
+
+        let mut end = 0;
+        for count in &mut sorted_ends {
+            end += *count;
+            *count = end;
+        }
+
+        self.sorted = sorted;
+        self.sorted_ends = sorted_ends;
+    }
+
+    pub fn path(&self, id: PathId) -> PathBuf {
+        let mut names = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.parent(current) {
+            names.push(self.name(self.nodes[current.0 as usize].name));
+            current = parent;
+        }
+
+        let mut path = PathBuf::from("/");
+        path.extend(names.into_iter().rev().map(OsStr::from_bytes));
+        path
+    }
+
+    fn name(&self, id: NameId) -> &[u8] {
+        name_bytes(&self.names, &self.name_ends, id.0)
+    }
+
+    fn intern(&mut self, name: &OsStr) -> NameId {
+        let bytes = name.as_bytes();
+        let names = &self.names;
+        let name_ends = &self.name_ends;
+        let hasher = &self.hasher;
+        self.name_index.reserve(|id| hasher.hash_one(name_bytes(names, name_ends, id)));
+
+        let hash = hasher.hash_one(bytes);
+        match self.name_index.find(hash, |id| name_bytes(names, name_ends, id) == bytes) {
+            Ok(id) => NameId(id),
+            Err(slot) => {
+                let id = self.name_ends.len() as u32;
+                self.names.extend_from_slice(bytes);
+                self.name_ends.push(self.names.len() as u32);
+                self.name_index.insert(slot, id);
+                NameId(id)
+            }
+        }
+    }
+}
+
+fn name_bytes<'a>(names: &'a [u8], name_ends: &[u32], id: u32) -> &'a [u8] {
+    let start = match id {
+        0 => 0,
+        _ => name_ends[id as usize - 1] as usize
+    };
+
+    &names[start..name_ends[id as usize] as usize]
+}
+
+impl Index {
+    fn new() -> Self {
+        Index { slots: vec![0; 16], len: 0 }
+    }
+
+    // Find the identifier whose key is equal, or else the empty slot where
+    // it should be inserted.
+    fn find<F>(&self, hash: u64, eq: F) -> Result<u32, usize>
+        where F: Fn(u32) -> bool
+    {
+        let mask = self.slots.len() - 1;
+        let mut slot = hash as usize & mask;
+        loop {
+            match self.slots[slot] {
+                0 => return Err(slot),
+                value if eq(value - 1) => return Ok(value - 1),
+                _ => slot = (slot + 1) & mask
+            }
+        }
+    }
+
+    fn insert(&mut self, slot: usize, id: u32) {
+        self.slots[slot] = id + 1;
+        self.len += 1;
+    }
+
+    // Grow the table before it becomes half full, rehashing every
+    // identifier.
+    fn reserve<H>(&mut self, hash: H)
+        where H: Fn(u32) -> u64
+    {
+        if (self.len + 1) * 2 <= self.slots.len() {
+            return;
+        }
+
+        let mask = self.slots.len() * 2 - 1;
+        let mut slots = vec![0; self.slots.len() * 2];
+        for value in self.slots.iter().filter(|value| **value != 0) {
+            let mut slot = hash(value - 1) as usize & mask;
+            while slots[slot] != 0 {
+                slot = (slot + 1) & mask;
+            }
+            slots[slot] = *value;
+        }
+
+        self.slots = slots;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(tree: &PathTree, id: PathId) -> Vec<&[u8]> {
+        tree.children(id).iter().map(|id| tree.file_name(*id).as_bytes()).collect()
+    }
+
+    #[test]
+    fn insert_and_find() {
+        let mut tree = PathTree::new();
+        let ls = tree.insert(Path::new("/usr/bin/ls"));
+        let lib = tree.insert(Path::new("/usr/lib"));
+        assert_eq!(tree.insert(Path::new("/usr/bin/ls")), ls);
+        assert_eq!(tree.find(Path::new("/usr/bin/ls")), Some(ls));
+        assert_eq!(tree.find(Path::new("/usr/lib")), Some(lib));
+        assert_eq!(tree.find(Path::new("/usr/bin")), tree.parent(ls));
+        assert_eq!(tree.find(Path::new("/")), Some(ROOT));
+        assert_eq!(tree.find(Path::new("/usr/bin/cp")), None);
+        assert_eq!(tree.find(Path::new("/bin/ls")), None);
+        assert_eq!(tree.find(Path::new("/usr/lib/ls")), None);
+    }
+
+    #[test]
+    fn parent_dir() {
+        let mut tree = PathTree::new();
+        let ls = tree.insert(Path::new("/usr/bin/ls"));
+        assert_eq!(tree.insert(Path::new("/usr/lib/../bin/ls")), ls);
+        assert_eq!(tree.insert(Path::new("/usr/./bin//ls")), ls);
+        assert_eq!(tree.insert(Path::new("/../usr/bin/ls")), ls);
+        assert_eq!(tree.find(Path::new("/usr/bin/../bin/ls")), Some(ls));
+        assert_eq!(tree.find(Path::new("/usr/bin/ls/../..")), tree.find(Path::new("/usr")));
+        assert_eq!(tree.find(Path::new("/..")), Some(ROOT));
+        let b = tree.insert(Path::new("/opt/a/../b"));
+        assert_eq!(tree.path(b), Path::new("/opt/b"));
+    }
+
+    #[test]
+    fn children_sorted() {
+        let mut tree = PathTree::new();
+        for path in ["/usr/bin/b", "/usr/bin/a-", "/usr/bin/C", "/usr/bin/a", "/etc", "/usr/bin/b"] {
+            tree.insert(Path::new(path));
+        }
+        tree.insert(Path::new(OsStr::from_bytes(b"/usr/bin/a\xff")));
+        assert!(tree.children(ROOT).is_empty());
+
+        tree.sort();
+        let usr = tree.find(Path::new("/usr")).unwrap();
+        let bin = tree.find(Path::new("/usr/bin")).unwrap();
+        let a = tree.find(Path::new("/usr/bin/a")).unwrap();
+        assert_eq!(names(&tree, ROOT), [&b"etc"[..], b"usr"]);
+        assert_eq!(names(&tree, usr), [&b"bin"[..]]);
+        assert_eq!(names(&tree, bin), [&b"C"[..], b"a", b"a-", b"a\xff", b"b"]);
+        assert!(tree.children(a).is_empty());
+    }
+
+    #[test]
+    fn path() {
+        let mut tree = PathTree::new();
+        let id = tree.insert(Path::new(OsStr::from_bytes(b"/opt/caf\xe9/a b")));
+        assert_eq!(tree.path(id).as_os_str().as_bytes(), b"/opt/caf\xe9/a b");
+        assert_eq!(tree.path(ROOT), Path::new("/"));
+        assert_eq!(tree.file_name(id), OsStr::new("a b"));
+        assert_eq!(tree.file_name(ROOT), OsStr::new(""));
+        assert_eq!(tree.parent(ROOT), None);
+    }
+
+    #[test]
+    fn growth() {
+        let mut tree = PathTree::new();
+        let paths: Vec<PathBuf> = (0..5000)
+            .map(|i| PathBuf::from(format!("/dir{}/file{}", i % 50, i)))
+            .collect();
+        let ids: Vec<PathId> = paths.iter().map(|path| tree.insert(path)).collect();
+        tree.sort();
+
+        for (path, id) in paths.iter().zip(&ids) {
+            assert_eq!(tree.find(path), Some(*id));
+            assert_eq!(tree.insert(path), *id);
+            assert_eq!(tree.path(*id), *path);
+        }
+
+        assert_eq!(tree.children(ROOT).len(), 50);
+        let dir = tree.find(Path::new("/dir7")).unwrap();
+        assert_eq!(tree.children(dir).len(), 100);
+        assert_eq!(tree.name_ends.len(), 1 + 50 + 5000);
+    }
+}
@@ -24,32 +24,78 @@
 #![allow(static_mut_refs)]
 
 use std::mem::MaybeUninit;
-use std::path::PathBuf;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component,Path,PathBuf};
 use std::sync::{Arc,Once};
 use std::{env,fs,process};
 
-use clap::{Arg,ArgAction,ArgMatches};
+use clap::{Arg,ArgAction,ArgMatches,Command as ClapCommand};
 use clap::parser::ValuesRef;
-use config::{Config,ConfigError,File};
+use config::{Config,ConfigError,File,FileFormat,FileSourceFile};
+use globset::{GlobBuilder,GlobSet,GlobSetBuilder};
+use regex::bytes::RegexSet;
 use serde::Deserialize;
 
+use crate::command::Command;
 use crate::symlink::Symlink;
 
+type ConfigFile = File<FileSourceFile,FileFormat>;
+
 static START: Once = Once::new();
 static mut INSTANCE: MaybeUninit<Arc<Settings>> = MaybeUninit::uninit();
 
 #[derive(Debug,Default,Deserialize)]
 pub struct Settings {
+    #[serde(skip)]
+    root: PathBuf,
+    #[serde(skip)]
+    eprefix: PathBuf,
+    #[serde(skip)]
+    paths: Option<Vec<PathBuf>>,
+    #[serde(skip)]
+    command: Command,
     pkg_dir: String,
+    #[serde(skip)]
     ignore_files: Option<Vec<PathBuf>>,
+    #[serde(skip)]
     ignore_paths: Option<Vec<PathBuf>>,
+    #[serde(skip)]
+    ignore_glob_set: Option<GlobSet>,
+    #[serde(skip)]
+    ignore_regex_set: Option<RegexSet>,
+    #[serde(skip)]
+    skip_fs_types: Option<Vec<String>>,
     links_to_usr: Option<Vec<Symlink>>,
     split_usr: bool,
+    one_file_system: bool,
     md5: bool,
+    md5_cache: bool,
+    catalog_cache: bool,
+    dir_cache: bool,
+    #[serde(skip)]
+    refresh_cache: bool,
+    cache_dir: String,
     mtime: bool,
+    missing: bool,
+    symlinks: bool,
+    dangling: bool,
+    strict: bool,
     verbose: bool
 }
 
+// List settings read from a single configuration layer. Lists are
+// appended across the defaults, each configuration file and the command
+// line, rather than replaced. An entry beginning with `!` removes an
+// entry inherited from an earlier layer.
+#[derive(Debug,Default,Deserialize)]
+struct Lists {
+    ignore_files: Option<Vec<String>>,
+    ignore_paths: Option<Vec<String>>,
+    ignore_globs: Option<Vec<String>>,
+    ignore_regex: Option<Vec<String>>,
+    skip_fs_types: Option<Vec<String>>
+}
+
 impl Settings {
     pub fn get() -> Arc<Settings> {
         START.call_once(|| {
@@ -71,26 +117,97 @@ impl Settings {
 
     fn init() -> Result<Self,ConfigError> {
         let args = parse_args();
-        let builder = Config::builder()
+        let root = read_root(&args)?;
+        let eprefix = read_eprefix(&args)?;
+        let eroot = root_path(&root, &eprefix);
+        let mut system_configs = vec![eroot.join("etc/cruft.yaml")];
+        system_configs.append(&mut config_fragments(&eroot.join("etc/cruft.d")));
+
+        let mut builder = Config::builder()
             .set_default("pkg_dir", "/var/db/pkg")?
             .set_default("split_usr", false)?
+            .set_default("one_file_system", false)?
             .set_default("md5", false)?
+            .set_default("md5_cache", true)?
+            .set_default("catalog_cache", true)?
+            .set_default("dir_cache", false)?
+            .set_default("cache_dir", "/var/cache/cruft")?
             .set_default("mtime", false)?
-            .set_default("verbose", false)?
-            .set_default::<&str, Option<Vec<String>>>("ignore_paths", None)?
-            .set_default::<&str, Option<Vec<String>>>("ignore_files", None)?
-            .add_source(File::with_name("/etc/cruft.yaml").required(false))
-            .add_source(File::with_name(&home_config()).required(false));
+            .set_default("missing", false)?
+            .set_default("symlinks", false)?
+            .set_default("dangling", false)?
+            .set_default("strict", false)?
+            .set_default("verbose", false)?;
+        for path in &system_configs {
+            builder = builder.add_source(system_config(path));
+        }
+        builder = builder.add_source(home_config());
+
+        let mut lists = Lists::defaults();
+        for path in &system_configs {
+            lists.append(Lists::read(system_config(path))?);
+        }
+        lists.append(Lists::read(home_config())?);
+        lists.append(Lists::from_args(&args));
 
         let conf = builder.build()?;
         let mut settings = Self::merge_args(conf.try_deserialize()?, &args);
-        settings.links_to_usr = read_links();
-        settings.split_usr = is_split_usr(settings.links_to_usr.is_some());
+        settings.set_lists(lists)?;
+        settings.links_to_usr = read_links(&eroot, &eprefix);
+        settings.split_usr = is_split_usr(&eroot, settings.links_to_usr.is_some(), settings.verbose);
+        settings.root = root;
+        settings.eprefix = eprefix;
+        settings.paths = settings.read_paths(&args);
+        settings.command = read_command(&args);
         Ok(settings)
     }
 
-    pub fn pkg_dir(&self) -> &str {
-        &self.pkg_dir
+    // The prefix directory beneath the root directory. This is where the
+    // file system scan begins.
+    pub fn eroot(&self) -> PathBuf {
+        self.root_path(&self.eprefix)
+    }
+
+    pub fn command(&self) -> &Command {
+        &self.command
+    }
+
+    // The directories where the file system scan begins. These are the
+    // paths specified on the command line, or the prefix directory.
+    pub fn scan_paths(&self) -> Vec<PathBuf> {
+        match &self.paths {
+            Some(paths) => paths.iter().map(|p| self.root_path(p)).collect(),
+            None => vec![self.eroot()]
+        }
+    }
+
+    // Whether a path recorded in the package database falls beneath one
+    // of the paths specified on the command line.
+    pub fn in_scope(&self, path: &Path) -> bool {
+        match &self.paths {
+            Some(paths) => paths.iter().any(|p| path.starts_with(p)),
+            None => true
+        }
+    }
+
+    pub fn pkg_dir(&self) -> PathBuf {
+        self.root_path(prefix_path(&self.eprefix, &self.pkg_dir))
+    }
+
+    // Translate a path as recorded in the package database into the
+    // corresponding path beneath the root directory.
+    pub fn root_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        root_path(&self.root, path)
+    }
+
+    // Translate a path beneath the root directory into the path that
+    // would be recorded in the package database.
+    pub fn db_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = path.as_ref();
+        match path.strip_prefix(&self.root) {
+            Ok(path) => Path::new("/").join(path),
+            Err(_) => path.to_path_buf()
+        }
     }
 
     pub fn ignore_files(&self) -> Option<&Vec<PathBuf>> {
@@ -101,35 +218,156 @@ impl Settings {
         self.ignore_paths.as_ref()
     }
 
-    pub fn links_to_usr(&self) -> Option<&Vec<Symlink>> {
-        self.links_to_usr.as_ref()
+    // Translate a path into its merged-usr location, e.g. `/bin/ls`
+    // becomes `/usr/bin/ls`, unless the system uses a split-usr layout.
+    pub fn usr_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let mut path = path.as_ref().to_path_buf();
+        if !self.split_usr {
+            if let Some(links) = &self.links_to_usr {
+                for link in links {
+                    if path.starts_with(link.src()) {
+                        let count = link.src().components().count();
+                        let pathbuf: PathBuf = path.components().skip(count).collect();
+                        path = PathBuf::from(link.dst()).join(pathbuf);
+                    }
+                }
+            }
+        }
+
+        path
     }
 
-    pub fn is_split_usr(&self) -> bool {
-        self.split_usr
+    // Whether a path matches one of the `ignore_globs` or `ignore_regex`
+    // patterns.
+    pub fn matches_ignore_pattern(&self, path: &Path) -> bool {
+        if let Some(globs) = &self.ignore_glob_set {
+            if globs.is_match(path) {
+                return true;
+            }
+        }
+
+        if let Some(regex) = &self.ignore_regex_set {
+            if regex.is_match(path.as_os_str().as_bytes()) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    pub fn skip_fs_types(&self) -> Option<&Vec<String>> {
+        self.skip_fs_types.as_ref()
+    }
+
+    pub fn one_file_system(&self) -> bool {
+        self.one_file_system
     }
 
     pub fn md5(&self) -> bool {
         self.md5
     }
 
+    // Whether MD5 sums are read from and saved to the cache.
+    pub fn md5_cache(&self) -> bool {
+        self.md5_cache
+    }
+
+    // Whether parsed CONTENTS files are read from and saved to the cache.
+    pub fn catalog_cache(&self) -> bool {
+        self.catalog_cache
+    }
+
+    // Whether directory listings are read from and saved to the cache.
+    pub fn dir_cache(&self) -> bool {
+        self.dir_cache
+    }
+
+    // Whether cached data is discarded and read again from the file system
+    // and package database.
+    pub fn refresh_cache(&self) -> bool {
+        self.refresh_cache
+    }
+
+    // The directory where cache files are stored. This is a path on the
+    // host system, regardless of the root directory.
+    pub fn cache_dir(&self) -> &Path {
+        Path::new(&self.cache_dir)
+    }
+
     pub fn mtime(&self) -> bool {
         self.mtime
     }
 
+    pub fn missing(&self) -> bool {
+        self.missing
+    }
+
+    pub fn symlinks(&self) -> bool {
+        self.symlinks
+    }
+
+    pub fn dangling(&self) -> bool {
+        self.dangling
+    }
+
+    // Whether a malformed line in a CONTENTS file is an error, rather than
+    // being skipped.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
     pub fn verbose(&self) -> bool {
         self.verbose
     }
 
+    fn set_lists(&mut self, lists: Lists) -> Result<(),ConfigError> {
+        self.ignore_files = resolve(lists.ignore_files).map(into_path_buf);
+        self.ignore_paths = resolve(lists.ignore_paths).map(into_path_buf);
+        self.ignore_glob_set = build_globs(resolve(lists.ignore_globs))?;
+        self.ignore_regex_set = build_regex(resolve(lists.ignore_regex))?;
+        self.skip_fs_types = resolve(lists.skip_fs_types);
+        Ok(())
+    }
+
     fn merge_args(mut settings: Self, args: &ArgMatches) -> Self {
+        if args.get_flag("one-file-system") {
+            settings.one_file_system = !settings.one_file_system;
+        }
+
         if args.get_flag("md5") {
             settings.md5 = !settings.md5;
         }
 
+        if args.get_flag("no-cache") {
+            settings.md5_cache = false;
+            settings.catalog_cache = false;
+            settings.dir_cache = false;
+        }
+
+        if args.get_flag("refresh-cache") {
+            settings.refresh_cache = true;
+        }
+
         if args.get_flag("mtime") {
             settings.mtime = !settings.mtime;
         }
 
+        if args.get_flag("missing") {
+            settings.missing = !settings.missing;
+        }
+
+        if args.get_flag("symlinks") {
+            settings.symlinks = !settings.symlinks;
+        }
+
+        if args.get_flag("dangling") {
+            settings.dangling = !settings.dangling;
+        }
+
+        if args.get_flag("strict") {
+            settings.strict = true;
+        }
+
         if args.get_flag("verbose") {
             settings.verbose = true;
         }
@@ -138,51 +376,141 @@ impl Settings {
             settings.pkg_dir = pkg_dir.clone();
         }
 
-        if let Some(paths) = args.get_many("ignore-path") {
-            let ignore_paths = match settings.ignore_paths {
-                Some(mut ignore_paths) => {
-                    ignore_paths.append(&mut into_path_buf(paths));
-                    ignore_paths
-                },
+        settings
+    }
 
-                None => into_path_buf(paths)
-            };
-            settings.ignore_paths = Some(ignore_paths);
+    fn read_paths(&self, args: &ArgMatches) -> Option<Vec<PathBuf>> {
+        let values = args.get_many::<PathBuf>("paths")?;
+        let mut paths: Vec<PathBuf> = values.map(|value| {
+            self.arg_path(value)
+        }).collect();
+
+        paths.sort();
+        paths.dedup_by(|b, a| b.starts_with(a));
+        Some(paths)
+    }
+
+    // Translate a path given on the command line into the path that would
+    // be recorded in the package database. Relative paths are relative to
+    // the current directory, which may be beneath the root directory.
+    pub fn arg_path<P: AsRef<Path>>(&self, value: P) -> PathBuf {
+        let path = value.as_ref();
+        let path = if path.is_absolute() {
+            normalize(path)
+        } else {
+            let cwd = env::current_dir().unwrap_or_default();
+            self.db_path(normalize(&cwd.join(path)))
+        };
+
+        self.usr_path(path)
+    }
+}
+
+fn default_skip_fs_types() -> Vec<String> {
+    ["proc", "sysfs", "devpts", "tmpfs", "devtmpfs", "cgroup", "cgroup2",
+     "debugfs", "securityfs", "nfs", "nfs4", "cifs", "smb3", "fuse.*", "overlay"]
+        .iter().map(|s| s.to_string()).collect()
+}
+
+impl Lists {
+    fn defaults() -> Self {
+        Lists {
+            skip_fs_types: Some(default_skip_fs_types()),
+            ..Default::default()
         }
+    }
 
-        if let Some(files) = args.get_many("ignore-file") {
-            let ignore_files = match settings.ignore_files {
-                Some(mut ignore_files) => {
-                    ignore_files.append(&mut into_path_buf(files));
-                    ignore_files
-                },
+    fn read(source: ConfigFile) -> Result<Self,ConfigError> {
+        Config::builder()
+            .add_source(source)
+            .build()?
+            .try_deserialize()
+    }
 
-                None => into_path_buf(files)
-            };
-            settings.ignore_files = Some(ignore_files);
+    fn from_args(args: &ArgMatches) -> Self {
+        Lists {
+            ignore_files: into_strings(args.get_many("ignore-file")),
+            ignore_paths: into_strings(args.get_many("ignore-path")),
+            ignore_globs: into_strings(args.get_many("ignore-glob")),
+            ignore_regex: into_strings(args.get_many("ignore-regex")),
+            skip_fs_types: into_strings(args.get_many("skip-fs-type"))
         }
+    }
 
-        settings
+    // Append the lists from a later configuration layer.
+    fn append(&mut self, other: Lists) {
+        self.ignore_files = merge(self.ignore_files.take(), other.ignore_files);
+        self.ignore_paths = merge(self.ignore_paths.take(), other.ignore_paths);
+        self.ignore_globs = merge(self.ignore_globs.take(), other.ignore_globs);
+        self.ignore_regex = merge(self.ignore_regex.take(), other.ignore_regex);
+        self.skip_fs_types = merge(self.skip_fs_types.take(), other.skip_fs_types);
     }
 }
 
-fn home_config() -> String {
+fn system_config(path: &Path) -> ConfigFile {
+    File::from(path).required(false)
+}
+
+fn home_config() -> ConfigFile {
     let home = env::var("HOME").unwrap();
-    format!("{}/.config/cruft.yaml", home)
+    File::with_name(&format!("{}/.config/cruft.yaml", home)).required(false)
+}
+
+// Configuration fragments in `/etc/cruft.d`, in lexical order.
+fn config_fragments(dir: &Path) -> Vec<PathBuf> {
+    let mut fragments: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension().is_some_and(|ext| ext == "yaml") &&
+                    path.is_file()
+            })
+            .collect(),
+        Err(_) => Vec::new()
+    };
+
+    fragments.sort();
+    fragments
 }
 
 fn parse_args() -> ArgMatches {
     command!()
+        .arg(Arg::new("root").short('r').long("root")
+            .help("Root directory of the system to scan")
+            .value_parser(value_parser!(String))
+            .value_name("PATH")
+            .global(true)
+            .action(ArgAction::Set)
+            .default_value("/"))
+        .arg(Arg::new("eprefix").short('e').long("eprefix")
+            .help("Offset prefix of a Gentoo Prefix installation")
+            .value_parser(value_parser!(String))
+            .value_name("PATH")
+            .global(true)
+            .action(ArgAction::Set))
         .arg(Arg::new("pkg-dir").short('d').long("pkg-dir")
             .help("Path to the Gentoo package database")
             .value_parser(value_parser!(String))
             .value_name("PATH")
+            .global(true)
             .action(ArgAction::Set)
             .default_value("/var/db/pkg"))
         .arg(arg!(-m --md5   "Calculate and compare MD5 sums (inverts config setting)")
             .action(ArgAction::SetTrue))
+        .arg(arg!(--"no-cache" "Do not read or update the caches")
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"refresh-cache" "Discard cached data and read everything again")
+            .global(true)
+            .action(ArgAction::SetTrue))
         .arg(arg!(-t --mtime "Compare file modification times (inverts config setting)")
             .action(ArgAction::SetTrue))
+        .arg(arg!(-M --missing "Display files recorded in the package database that are missing (inverts config setting)")
+            .action(ArgAction::SetTrue))
+        .arg(arg!(-l --symlinks "Compare symlink targets (inverts config setting)")
+            .action(ArgAction::SetTrue))
+        .arg(arg!(-L --dangling "Display symlinks whose targets do not exist (inverts config setting)")
+            .action(ArgAction::SetTrue))
         .arg(Arg::new("ignore-file").short('f').long("ignore-file")
             .help("Add file to ignore when traversing the directory tree")
             .action(ArgAction::Append)
@@ -191,59 +519,232 @@ fn parse_args() -> ArgMatches {
             .help("Add path to ignore when traversing the directory tree")
             .action(ArgAction::Append)
             .value_name("PATH"))
+        .arg(Arg::new("ignore-glob").short('g').long("ignore-glob")
+            .help("Add glob pattern to ignore when traversing the directory tree")
+            .action(ArgAction::Append)
+            .value_name("GLOB"))
+        .arg(Arg::new("ignore-regex").short('E').long("ignore-regex")
+            .help("Add regular expression to ignore when traversing the directory tree")
+            .action(ArgAction::Append)
+            .value_name("REGEX"))
+        .arg(arg!(-x --"one-file-system" "Do not cross file system boundaries (inverts config setting)")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("skip-fs-type").short('s').long("skip-fs-type")
+            .help("Add file system type to skip when traversing the directory tree")
+            .action(ArgAction::Append)
+            .value_name("TYPE"))
+        .arg(arg!(--strict "Fail on malformed CONTENTS files instead of skipping the damaged lines")
+            .global(true)
+            .action(ArgAction::SetTrue))
         .arg(arg!(-v --verbose "Display warnings on STDERR")
+            .global(true)
             .action(ArgAction::SetTrue))
+        .arg(Arg::new("paths")
+            .help("Restrict the scan to the given paths")
+            .value_parser(value_parser!(PathBuf))
+            .action(ArgAction::Append)
+            .value_name("PATH"))
+        .subcommand(ClapCommand::new("check")
+            .about("Verify the files installed by the given packages")
+            .arg(Arg::new("atoms")
+                .help("Packages to verify")
+                .action(ArgAction::Append)
+                .required(true)
+                .value_name("ATOM")))
+        .subcommand(ClapCommand::new("collisions")
+            .about("Display paths recorded by more than one package"))
+        .subcommand(ClapCommand::new("owner")
+            .about("Display the packages that own the given paths")
+            .arg(Arg::new("paths")
+                .help("Paths to look up in the package database")
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append)
+                .required(true)
+                .value_name("PATH")))
         .get_matches()
 }
 
-fn into_path_buf<'a>(values: ValuesRef<'a, String>) -> Vec<PathBuf> {
-    values.map(|s: &String| PathBuf::from(s)).collect()
+fn into_path_buf(values: Vec<String>) -> Vec<PathBuf> {
+    values.into_iter().map(PathBuf::from).collect()
 }
 
-fn is_split_usr(links_exist: bool) -> bool {
-    does_profile_contain_split_usr() && !links_exist
+fn into_strings(values: Option<ValuesRef<'_, String>>) -> Option<Vec<String>> {
+    values.map(|values| values.cloned().collect())
 }
 
-fn does_profile_contain_split_usr() -> bool {
-    let link = fs::read_link("/etc/portage/make.profile")
-        .expect("Unable to read `/etc/portage/make.profile`");
+fn merge<T>(list: Option<Vec<T>>, other: Option<Vec<T>>) -> Option<Vec<T>> {
+    match (list, other) {
+        (Some(mut list), Some(mut other)) => {
+            list.append(&mut other);
+            Some(list)
+        },
 
-    let os_str = link.as_os_str();
-    let link_str = os_str.to_str()
-        .expect("Path contains invalid Unicode");
-    if link_str.contains("/split-usr") {
-        return true;
+        (list, None) => list,
+        (None, other) => other
     }
+}
+
+// Apply negated `!` entries to the entries preceding them.
+fn resolve(list: Option<Vec<String>>) -> Option<Vec<String>> {
+    let list = list?;
+    let mut resolved: Vec<String> = Vec::with_capacity(list.len());
+    for entry in list {
+        match entry.strip_prefix('!') {
+            Some(negated) => resolved.retain(|e| e != negated),
+            None => resolved.push(entry)
+        }
+    }
+
+    Some(resolved)
+}
+
+fn build_globs(globs: Option<Vec<String>>) -> Result<Option<GlobSet>,ConfigError> {
+    let globs = match globs {
+        Some(globs) => globs,
+        None => return Ok(None)
+    };
+
+    let mut builder = GlobSetBuilder::new();
+    for glob in &globs {
+        let glob = GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()
+            .map_err(|err| ConfigError::Message(format!("Invalid glob: {}", err)))?;
+        builder.add(glob);
+    }
+
+    builder.build()
+        .map(Some)
+        .map_err(|err| ConfigError::Message(format!("Invalid glob: {}", err)))
+}
+
+fn build_regex(regex: Option<Vec<String>>) -> Result<Option<RegexSet>,ConfigError> {
+    match regex {
+        Some(regex) => RegexSet::new(regex)
+            .map(Some)
+            .map_err(|err| ConfigError::Message(format!("Invalid regex: {}", err))),
+        None => Ok(None)
+    }
+}
+
+fn read_command(args: &ArgMatches) -> Command {
+    match args.subcommand() {
+        Some(("check", args)) => {
+            Command::Check(args.get_many::<String>("atoms")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default())
+        },
+
+        Some(("collisions", _)) => Command::Collisions,
+        Some(("owner", args)) => {
+            Command::Owner(args.get_many::<PathBuf>("paths")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default())
+        },
+
+        _ => Command::Scan
+    }
+}
+
+fn read_root(args: &ArgMatches) -> Result<PathBuf,ConfigError> {
+    let root = args.get_one::<String>("root").unwrap();
+    fs::canonicalize(root).map_err(|err| {
+        ConfigError::Message(format!("Invalid root `{}`: {}", root, err))
+    })
+}
+
+fn read_eprefix(args: &ArgMatches) -> Result<PathBuf,ConfigError> {
+    match args.get_one::<String>("eprefix") {
+        Some(eprefix) => {
+            let path = PathBuf::from(eprefix);
+            if !path.is_absolute() {
+                let msg = format!("EPREFIX `{}` is not an absolute path", eprefix);
+                return Err(ConfigError::Message(msg));
+            }
+            Ok(path)
+        },
+
+        None => Ok(PathBuf::from("/"))
+    }
+}
+
+// Lexically remove `.` and `..` components from an absolute path.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => { },
+            Component::ParentDir => { normal.pop(); },
+            component => normal.push(component)
+        }
+    }
+
+    normal
+}
+
+fn root_path<P: AsRef<Path>>(root: &Path, path: P) -> PathBuf {
+    let path = path.as_ref();
+    let path = path.strip_prefix("/").unwrap_or(path);
+    if path.as_os_str().is_empty() {
+        return root.to_path_buf();
+    }
+
+    root.join(path)
+}
+
+fn prefix_path<P: AsRef<Path>>(eprefix: &Path, path: P) -> PathBuf {
+    let path = path.as_ref();
+    eprefix.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+fn is_split_usr(eroot: &Path, links_exist: bool, verbose: bool) -> bool {
+    does_profile_contain_split_usr(eroot, verbose) && !links_exist
+}
+
+// A missing or unreadable profile is treated as not split-usr.
+fn does_profile_contain_split_usr(eroot: &Path, verbose: bool) -> bool {
+    let profile = root_path(eroot, "/etc/portage/make.profile");
+    let link = match fs::read_link(&profile) {
+        Ok(link) => link,
+        Err(err) => {
+            if verbose {
+                eprintln!("Unable to read `{}`: {}", profile.display(), err);
+            }
+
+            return false;
+        }
+    };
 
-    false
+    link.to_string_lossy().contains("/split-usr")
 }
 
-fn read_links() -> Option<Vec<Symlink>> {
+fn read_links(eroot: &Path, eprefix: &Path) -> Option<Vec<Symlink>> {
     let mut links: Option<Vec<Symlink>> = None;
     for path in ["/bin", "/lib", "/lib64", "/sbin"] {
-        let link = match fs::read_link(path) {
-            Ok(link) => PathBuf::from("/").join(link),
+        let link = match fs::read_link(root_path(eroot, path)) {
+            Ok(link) => eprefix.join(link),
             Err(_) => continue
         };
 
-        let path = PathBuf::from(path);
+        let path = prefix_path(eprefix, path);
         match &mut links {
             Some(vec) => vec.push(Symlink::new(path, link)),
             None => links = Some(Vec::from(&[Symlink::new(path, link)]))
         }
     }
 
-    if let Ok(link) = fs::read_link("/usr/sbin") {
+    if let Ok(link) = fs::read_link(root_path(eroot, "/usr/sbin")) {
+        let usr = prefix_path(eprefix, "/usr");
         let link = if link.components().count() == 1 {
-            Some(PathBuf::from("/usr").join(link))
-        } else if link.starts_with("/usr") {
-            Some(PathBuf::from(link))
+            Some(usr.join(link))
+        } else if link.starts_with(&usr) {
+            Some(link)
         } else {
             None
         };
 
         if let Some(link) = link {
-            let path = PathBuf::from("/usr/sbin");
+            let path = prefix_path(eprefix, "/usr/sbin");
 
             match &mut links {
                 Some(vec) => vec.push(Symlink::new(path, link)),